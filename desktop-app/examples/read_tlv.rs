@@ -1,159 +1,81 @@
-use std::{collections::HashMap, fs::read, path::PathBuf};
-
-use shared::data::{
-    CertificateHolder, Engine, ExhaustEmisions, Mass, MaximumTowableMass, PersonalData,
-    Registration, SeatingCapacity, Vehicle, VehicleOwner,
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
 };
+
 use clap::Parser;
 use color_eyre::Result;
-use iso7816_tlv::ber::{
-    Tlv,
-    Value::{Constructed, Primitive},
+use desktop_app::{
+    card_reading::File,
+    dump::{CardDump, MANIFEST_FILE},
+    parsing::{parse_files, CardContents},
+    reader::require_registration_number,
+    server::{Connection, Credentials, Server, DEFAULT_TIMEOUT},
 };
 
 #[derive(Debug, Parser)]
 struct Arguments {
-    data_path: String,
+    /// A directory written by the `dump` command of the desktop app, or one holding only the
+    /// `RegistrationA.data`, `RegistrationB.data` and `RegistrationC.data` files of a card.
+    data_path: PathBuf,
+    /// Host and port of the server, optionally starting with https://.
+    #[arg(long, default_value = "localhost:8000")]
+    server: String,
+    #[arg(long, requires = "password")]
+    username: Option<String>,
+    #[arg(long, requires = "username")]
+    password: Option<String>,
+    /// A PEM file with a certificate authority to trust on top of the system ones.
+    #[arg(long)]
+    ca_certificate: Option<PathBuf>,
+    /// The SHA-256 fingerprint of the only server certificate to accept.
+    #[arg(long)]
+    pinned_certificate: Option<String>,
+    /// How many seconds to wait for the server.
+    #[arg(long, default_value_t = DEFAULT_TIMEOUT.as_secs())]
+    timeout: u64,
 }
 
 fn main() -> Result<()> {
     let args = Arguments::parse();
 
-    let path = PathBuf::from(args.data_path);
-    let reg_a = read(path.join("RegistrationA.data"))?;
-    let reg_b = read(path.join("RegistrationB.data"))?;
-    let reg_c = read(path.join("RegistrationC.data"))?;
-
-    let reg_a = Tlv::parse_all(&reg_a);
-    let reg_b = Tlv::parse_all(&reg_b);
-    let reg_c = Tlv::parse_all(&reg_c);
-
-    let full_reg = [reg_a, reg_b, reg_c].concat();
-    let hash_map = tlv_to_hash_map(&full_reg);
-    let registration = hash_map_to_registration(&hash_map);
+    let contents = load(&args.data_path)?;
+    require_registration_number(&contents)?;
+    let registration = contents.registration;
 
     let json = serde_json::to_string_pretty(&registration)?;
 
     println!("{json}");
 
-    upload(&registration, "localhost:8000")?;
-    Ok(())
-}
-
-fn upload(registration: &Registration, upload_address: &str) -> Result<(), reqwest::Error> {
-    let client = reqwest::blocking::ClientBuilder::new().build()?;
-    client
-        .post(format!("http://{upload_address}/registration"))
-        .json(&registration)
-        .send()?;
-    println!(
-        "Uploaded sucefully. Should be available under: http://{upload_address}/registration/{}",
-        registration.registration_number
-    );
-    Ok(())
-}
-
-fn tlv_to_hash_map<'a>(reg: &'a Vec<Tlv>) -> HashMap<String, &'a Vec<u8>> {
-    let mut hash_map: HashMap<String, &'a Vec<u8>> = HashMap::new();
-
-    for tlv in reg {
-        add_values(tlv, &mut hash_map);
-    }
-
-    hash_map
-}
-
-fn add_values<'a>(tlv: &'a Tlv, hash_map: &mut HashMap<String, &'a Vec<u8>>) {
-    match tlv.value() {
-        Constructed(inner) => {
-            for inner_tlv in inner {
-                add_values(inner_tlv, hash_map);
-            }
-        }
-        Primitive(prim) => {
-            hash_map.insert(hex::encode(tlv.tag().to_bytes()), prim);
-        }
-    }
-}
-
-fn hash_map_to_registration(hash_map: &HashMap<String, &Vec<u8>>) -> Registration {
-    Registration {
-        issuer_state: hash_map.string("9F33"),
-        issuer_authority: hash_map.string("9F35"),
-        document_number: hash_map.string("9F38"),
-        registration_number: hash_map.string("81"),
-        date_of_first_registration: hash_map.string("82"),
-        personal_data: PersonalData {
-            certificate_holder: CertificateHolder {
-                surname_or_business_name: hash_map.string("83"),
-                other_name_or_initials: hash_map.string("84"),
-                address: hash_map.string("85"),
-            },
-            vehicles_owner: hash_map.vehicle_owner(),
-        },
-        vehicle: Vehicle {
-            make: hash_map.string("87"),
-            vehicle_type: hash_map.string("88"),
-            commercial_descriptons: hash_map.string("89"),
-        },
-        vehicle_identification_number: hash_map.string("8A"),
-        mass: Mass {
-            maximum_technically_permissible_laden_mass: hash_map.string("8B"),
-            maximum_permissible_laden_mass_of_the_vehicle_in_service: hash_map.string("86"),
-            maximum_permissible_laden_mass_of_the_whole_vehicle_in_service: hash_map.string("97"),
-        },
-        vehicle_mass_with_body: hash_map.string("8C"),
-        period_of_validity: hash_map.string("8D"),
-        date_of_registration: hash_map.string("8E"),
-        type_approval_number: hash_map.string("8F"),
-        engine: Engine {
-            capacity: hash_map.string("90"),
-            max_net_power: hash_map.string("91"),
-            fuel_type: hash_map.string("92"),
-        },
-        power_weight_ratio: hash_map.string("93"),
-        seating_capacity: SeatingCapacity {
-            number_of_seats: hash_map.string("94"),
-            nunmber_of_standing_places: hash_map.string("95"),
+    let connection = Connection {
+        credentials: Credentials {
+            username: args.username.unwrap_or_default(),
+            password: args.password.unwrap_or_default(),
         },
-        vechicle_category: hash_map.string("98"),
-        maximum_towable_mass: MaximumTowableMass {
-            braked: hash_map.string("9B"),
-            unbraked: hash_map.string("9C"),
-        },
-        colour: hash_map.string("9F24"),
-        maximum_speed: hash_map.string("25"),
-        exhaust_emissions: ExhaustEmisions {
-            environmental_category: hash_map.string("9F32"),
-        },
-    }
-}
-
-trait HashMapToRegistrationHelper {
-    fn string(&self, path: &str) -> String;
-    fn vehicle_owner(&self) -> VehicleOwner;
+        ca_certificate: args.ca_certificate,
+        pinned_certificate: args.pinned_certificate,
+        timeout: Duration::from_secs(args.timeout),
+        ..Connection::new(&args.server)
+    };
+    Server::connect(&connection)?.upload(&registration)?;
+    Ok(())
 }
 
-impl HashMapToRegistrationHelper for HashMap<String, &Vec<u8>> {
-    fn string(&self, path: &str) -> String {
-        if let Some(bytes) = self.get(&path.to_lowercase()) {
-            String::from_utf8_lossy(bytes).to_string()
-        } else {
-            String::from("Not found")
-        }
+/// Loads a dump with its manifest, checking the files against it, or else parses the registration
+/// files found in the directory.
+fn load(path: &Path) -> Result<CardContents> {
+    if path.join(MANIFEST_FILE).exists() {
+        return Ok(CardDump::load(path)?.contents());
     }
-
-    fn vehicle_owner(&self) -> VehicleOwner {
-        if let Some(val) = self.get("86") {
-            if **val == vec![0x00u8] {
-                VehicleOwner::Yes
-            } else if **val == vec![0x01u8] {
-                VehicleOwner::No
-            } else {
-                VehicleOwner::Unknown
-            }
-        } else {
-            VehicleOwner::Unknown
-        }
+    let mut files = HashMap::new();
+    for file in [
+        File::RegistrationA,
+        File::RegistrationB,
+        File::RegistrationC,
+    ] {
+        files.insert(file, fs::read(path.join(file.file_name()))?);
     }
+    Ok(CardContents::new(&parse_files(&files)))
 }
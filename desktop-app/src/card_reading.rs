@@ -1,22 +1,27 @@
-use crate::parsing::combine_registrations;
+use crate::parsing::{parse_files, CardContents};
 use iso7816_tlv::{
     ber::{Tag, Tlv, Value},
     TlvError,
 };
 use log::debug;
 use pcsc::{Card, MAX_BUFFER_SIZE};
-use shared::data::Registration;
 use std::collections::HashMap;
 use thiserror::Error;
 
-use crate::card_reading::select_file::{retrieve_file, File};
+use crate::card_reading::select_file::retrieve_file;
+pub use crate::card_reading::select_file::File;
 
-/// Read all regisration files from the card and combines their data into the [``Registration``] struct for easier use.
+/// Read all regisration files from the card and combines their data into the [``shared::data::Registration``] struct for easier use,
+/// keeping the raw values next to it.
+/// `progress` is called before each file is read. Returning `false` from it stops the reading.
 ///
 /// # Errors
 ///
-/// This function will return an error if an error occured whilst reading the card.
-pub fn read_card(card: &Card) -> Result<Registration, CardReadingError> {
+/// This function will return an error if an error occured whilst reading the card or if it was cancelled.
+pub fn read_card(
+    card: &Card,
+    progress: impl FnMut(File) -> bool,
+) -> Result<CardContents, CardReadingError> {
     if !is_evrc_card(card)? {
         Err(CardReadingError::NotAneVrc)?;
     }
@@ -28,21 +33,29 @@ pub fn read_card(card: &Card) -> Result<Registration, CardReadingError> {
             File::RegistrationB,
             File::RegistrationC,
         ],
+        progress,
     )?;
 
-    let registrations: Vec<Tlv> = files
-        .iter()
-        .filter_map(|(file, bytes)| {
-            if *file == File::FSOd {
-                None
-            } else {
-                Some(Tlv::parse_all(bytes))
-            }
-        })
-        .collect::<Vec<Vec<Tlv>>>()
-        .concat();
+    let registrations = parse_files(&files);
+
+    Ok(CardContents::new(&registrations))
+}
+
+/// Reads all files from the card as they are stored on it, including the security object.
+/// `progress` is called before each file is read. Returning `false` from it stops the reading.
+///
+/// # Errors
+///
+/// This function will return an error if an error occured whilst reading the card or if it was cancelled.
+pub fn dump_card(
+    card: &Card,
+    progress: impl FnMut(File) -> bool,
+) -> Result<HashMap<File, Vec<u8>>, CardReadingError> {
+    if !is_evrc_card(card)? {
+        Err(CardReadingError::NotAneVrc)?;
+    }
 
-    Ok(combine_registrations(&registrations))
+    read_files(card, File::ALL.to_vec(), progress)
 }
 
 /// The errors that can occur during the card reading process.
@@ -51,6 +64,9 @@ pub enum CardReadingError {
     /// The card is not a eVRC card.
     #[error("The card is not a eVRC card.")]
     NotAneVrc,
+    /// Reading was stopped before all files were read.
+    #[error("Reading the card was cancelled.")]
+    Cancelled,
     /// Got an unsuccessful response from card. Contains the command sent and the response received.
     #[error("Got an unsuccessful response from card. Command: {0:?}. Response: {1:?}")]
     UnsuccesfulResponseFromCard(Vec<u8>, Vec<u8>),
@@ -83,15 +99,22 @@ fn is_evrc_card(card: &Card) -> Result<bool, CardReadingError> {
     Ok(response == SELECT_EVRC_APPLICATION_EXPECTED_RESPONSE)
 }
 
-/// .
+/// Reads the files from the card, calling `progress` before each one.
 ///
 /// # Errors
 ///
-/// This function will return an error if an error occured whilst reading the card.
-fn read_files(card: &Card, files: Vec<File>) -> Result<HashMap<File, Vec<u8>>, CardReadingError> {
+/// This function will return an error if an error occured whilst reading the card or if `progress` returned `false`.
+fn read_files(
+    card: &Card,
+    files: Vec<File>,
+    mut progress: impl FnMut(File) -> bool,
+) -> Result<HashMap<File, Vec<u8>>, CardReadingError> {
     let mut map = HashMap::new();
 
     for file in files {
+        if !progress(file) {
+            Err(CardReadingError::Cancelled)?;
+        }
         let bytes = retrieve_file(card, file)?;
         map.insert(file, bytes);
     }
@@ -168,6 +191,25 @@ mod select_file {
     }
 
     impl File {
+        /// Every file on the card.
+        pub const ALL: [File; 4] = [
+            File::RegistrationA,
+            File::RegistrationB,
+            File::RegistrationC,
+            File::FSOd,
+        ];
+
+        /// The name the file is saved under when the card is dumped.
+        #[must_use]
+        pub fn file_name(&self) -> &'static str {
+            match self {
+                File::FSOd => "FSOd.data",
+                File::RegistrationA => "RegistrationA.data",
+                File::RegistrationB => "RegistrationB.data",
+                File::RegistrationC => "RegistrationC.data",
+            }
+        }
+
         /// Returns a reference to the binary identifier of this [`File`].
         #[must_use]
         pub fn binary_identifier(&self) -> &[u8; 2] {
@@ -0,0 +1,337 @@
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use clap::{Args, Subcommand, ValueEnum};
+use log::{error, info};
+use thiserror::Error;
+
+use crate::{
+    dump::{CardDump, DumpError},
+    parsing::{CardContents, FIELDS},
+    queue::{QueueError, UploadQueue},
+    reader::{self, InsertedCard, Progress, Reader, RecentCards},
+    server::{Server, ServerError},
+    settings::Settings,
+};
+
+/// How long `watch` waits for a card before it looks at the upload queue and for new readers
+/// again.
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Shown below the help, so scripts know what to expect.
+pub const EXIT_CODES: &str = "Exit codes:
+  0  Success
+  1  Any other error, like output that could not be written
+  2  Invalid arguments
+  3  Invalid settings, or a server that can not be set up
+  4  No card reader, or not the one asked for
+  5  No card in the reader
+  6  The card could not be read
+  7  The card was read, but could not be uploaded
+  8  The dump could not be written or loaded";
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Lists the connected card readers and the state of their card.
+    ListReaders,
+    /// Reads the card and prints the registration.
+    Read {
+        #[command(flatten)]
+        card: CardArgs,
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+    },
+    /// Reads the card and uploads it.
+    Upload {
+        #[command(flatten)]
+        card: CardArgs,
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+    /// Reads and uploads every card that is inserted, until stopped. Cards that could not be
+    /// uploaded are queued and tried again.
+    Watch {
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+    /// Writes the files of the card into a directory, as they are stored on the card, with a
+    /// manifest of where and when they were read.
+    Dump {
+        #[command(flatten)]
+        card: CardArgs,
+        directory: PathBuf,
+    },
+    /// Parses a dump and prints the registration, like `read` does for a card.
+    ParseDump {
+        directory: PathBuf,
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+    },
+    /// Parses a dump and uploads it.
+    UploadDump {
+        directory: PathBuf,
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct CardArgs {
+    /// The reader holding the card. Defaults to the preferred reader of the settings if it is
+    /// connected, or else the first reader holding a card.
+    #[arg(long)]
+    reader: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ServerArgs {
+    /// The server to upload to instead of the one in the settings, e.g. localhost:8000 or
+    /// https://vehikular.example.com. The other connection settings still apply.
+    #[arg(long)]
+    server: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Json,
+    Yaml,
+    /// A header with the names of the fields and a line with their values.
+    Csv,
+}
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("No card reader is connected.")]
+    NoReaders,
+    #[error(transparent)]
+    Reader(#[from] reader::Error),
+    #[error(transparent)]
+    Server(#[from] ServerError),
+    #[error(transparent)]
+    Queue(#[from] QueueError),
+    #[error(transparent)]
+    Dump(#[from] DumpError),
+    #[error("Could not write the output: {0}")]
+    Output(String),
+}
+
+impl CliError {
+    /// See [`EXIT_CODES`].
+    fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Server(
+                ServerError::Invalid(_) | ServerError::Certificate(..) | ServerError::Setup(_),
+            ) => 3,
+            CliError::NoReaders
+            | CliError::Reader(
+                reader::Error::Pcsc(_)
+                | reader::Error::ReaderNotFound
+                | reader::Error::PnpNotficationAsReader,
+            ) => 4,
+            CliError::Reader(reader::Error::CardNotFound) => 5,
+            CliError::Reader(_) => 6,
+            CliError::Server(_) => 7,
+            CliError::Dump(_) => 8,
+            CliError::Queue(_) | CliError::Output(_) => 1,
+        }
+    }
+}
+
+fn output(err: impl ToString) -> CliError {
+    CliError::Output(err.to_string())
+}
+
+/// Runs the command and reports how it went.
+pub fn run(command: Command, settings: &Settings) -> ExitCode {
+    let result = match command {
+        Command::ListReaders => list_readers(),
+        Command::Read { card, format } => read(settings, &card, format),
+        Command::Upload { card, server } => upload(settings, &card, &server),
+        Command::Watch { server } => watch(settings, &server),
+        Command::Dump { card, directory } => dump(settings, &card, &directory),
+        Command::ParseDump { directory, format } => parse_dump(&directory, format),
+        Command::UploadDump { directory, server } => upload_dump(settings, &directory, &server),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{err}");
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+fn progress(step: Progress) -> bool {
+    info!("{step}");
+    true
+}
+
+fn list_readers() -> Result<(), CliError> {
+    let mut reader = Reader::new()?;
+    reader.update_readers()?;
+    for (name, state) in reader.card_states() {
+        println!("{name}\t{state}");
+    }
+    Ok(())
+}
+
+/// Finds the card to work with, see [`CardArgs::reader`].
+fn find_card(settings: &Settings, args: &CardArgs) -> Result<InsertedCard, CliError> {
+    let mut reader = Reader::new()?;
+    reader.update_readers()?;
+    let readers = reader.get_readers();
+    let name = match (&args.reader, &settings.preferred_reader) {
+        (Some(name), _) => name,
+        (None, Some(preferred)) if readers.contains(preferred) => preferred,
+        // The first empty reader is as good as any to say that there is no card.
+        _ => readers
+            .iter()
+            .find(|name| reader.find_card(name).is_ok())
+            .or(readers.first())
+            .ok_or(CliError::NoReaders)?,
+    };
+    Ok(reader.find_card(name)?)
+}
+
+/// The server in the settings, or the one given instead.
+fn connect(settings: &Settings, args: &ServerArgs) -> Result<Server, CliError> {
+    let mut connection = settings.connection();
+    if let Some(address) = &args.server {
+        connection.address = address.clone();
+    }
+    Ok(Server::connect(&connection)?)
+}
+
+fn read(settings: &Settings, card: &CardArgs, format: Format) -> Result<(), CliError> {
+    let contents = find_card(settings, card)?.read(progress)?;
+    print(&contents, format)
+}
+
+fn print(contents: &CardContents, format: Format) -> Result<(), CliError> {
+    let mut stdout = io::stdout().lock();
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut stdout, &contents.registration).map_err(output)?;
+            writeln!(stdout).map_err(output)?;
+        }
+        Format::Yaml => {
+            serde_yaml::to_writer(&mut stdout, &contents.registration).map_err(output)?
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(stdout);
+            writer
+                .write_record(FIELDS.iter().map(|(_, name)| name))
+                .map_err(output)?;
+            writer
+                .write_record(FIELDS.iter().map(|(tag, _)| {
+                    contents
+                        .raw
+                        .get(*tag)
+                        .map(|value| String::from_utf8_lossy(value).into_owned())
+                        .unwrap_or_default()
+                }))
+                .map_err(output)?;
+            writer.flush().map_err(output)?;
+        }
+    }
+    Ok(())
+}
+
+fn upload(settings: &Settings, card: &CardArgs, server: &ServerArgs) -> Result<(), CliError> {
+    // Invalid settings are found out before the card is read.
+    let server = connect(settings, server)?;
+    let contents = find_card(settings, card)?.read(progress)?;
+    server.upload(&contents.registration)?;
+    println!("Uploaded {}", contents.registration.registration_number);
+    Ok(())
+}
+
+fn watch(settings: &Settings, server: &ServerArgs) -> Result<(), CliError> {
+    let server = connect(settings, server)?;
+    let mut queue = UploadQueue::load()?;
+    let mut reader = Reader::new()?;
+    let mut recent = RecentCards::new(settings.dedupe_window());
+    info!("Watching for cards.");
+
+    loop {
+        for name in reader.wait_for_insertions(POLL_TIMEOUT)? {
+            // A card that can not be read is reported, but does not stop the watching.
+            let contents = match reader.find_card(&name).and_then(|card| card.read(progress)) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    error!("Could not read the card in {name}: {err}");
+                    continue;
+                }
+            };
+            let registration_number = &contents.registration.registration_number;
+            if let Some(ago) = recent.note(&contents.registration, Instant::now()) {
+                println!(
+                    "Skipped {registration_number}, it was read {} seconds ago",
+                    ago.as_secs()
+                );
+                continue;
+            }
+            match server.upload(&contents.registration) {
+                Ok(()) => println!("Uploaded {registration_number}"),
+                Err(ServerError::AlreadyExists) => {
+                    println!("{registration_number} is already on the server");
+                }
+                Err(err) => {
+                    error!("Could not upload {registration_number}: {err}");
+                    queue.push(
+                        contents.registration.clone(),
+                        Some(name.clone()),
+                        err.to_string(),
+                        err.is_temporary(),
+                    )?;
+                    println!("Queued {registration_number}: {err}");
+                }
+            }
+        }
+        upload_due(&server, &mut queue)?;
+    }
+}
+
+/// Uploads the queued cards that are due, one after another.
+fn upload_due(server: &Server, queue: &mut UploadQueue) -> Result<(), QueueError> {
+    while let Some(item) = queue.next_due() {
+        let registration_number = &item.registration.registration_number;
+        let result = match server.upload(&item.registration) {
+            Ok(()) | Err(ServerError::AlreadyExists) => {
+                println!("Uploaded queued {registration_number}");
+                Ok(())
+            }
+            Err(err) => {
+                error!("The queued upload of {registration_number} failed: {err}");
+                Err((err.to_string(), err.is_temporary()))
+            }
+        };
+        queue.finished(item.id, result)?;
+    }
+    Ok(())
+}
+
+fn dump(settings: &Settings, card: &CardArgs, directory: &Path) -> Result<(), CliError> {
+    let dump = find_card(settings, card)?.dump(progress)?;
+    for path in dump.save(directory)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn parse_dump(directory: &Path, format: Format) -> Result<(), CliError> {
+    print(&CardDump::load(directory)?.contents(), format)
+}
+
+fn upload_dump(settings: &Settings, directory: &Path, server: &ServerArgs) -> Result<(), CliError> {
+    let server = connect(settings, server)?;
+    let contents = CardDump::load(directory)?.contents();
+    reader::require_registration_number(&contents)?;
+    server.upload(&contents.registration)?;
+    println!("Uploaded {}", contents.registration.registration_number);
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    card_reading::File,
+    parsing::{parse_files, CardContents},
+    settings::write_private,
+};
+
+/// The name the manifest is saved under, next to the files.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, Error)]
+pub enum DumpError {
+    #[error("Could not access {0}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("The manifest is broken: {0}")]
+    Manifest(#[from] serde_json::Error),
+    #[error("{0} is missing from the dump.")]
+    Missing(String),
+    #[error("{0} does not match the hash in the manifest. Has it been changed?")]
+    Modified(&'static str),
+}
+
+/// Where and when a dump was made, and what it should contain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub reader: String,
+    /// The answer to reset of the card, in hex.
+    pub atr: String,
+    pub read_at: NaiveDateTime,
+    /// The SHA-256 of every file, in hex, by its file name.
+    pub files: BTreeMap<String, String>,
+}
+
+/// The files of a card as they are stored on it, so they can be parsed again later, e.g. by a
+/// newer version of the app, or uploaded once the server can be reached. Saved as one file per
+/// card file plus a [`Manifest`].
+#[derive(Debug, Clone)]
+pub struct CardDump {
+    pub manifest: Manifest,
+    pub files: HashMap<File, Vec<u8>>,
+}
+
+impl CardDump {
+    pub fn new(reader: String, atr: &[u8], files: HashMap<File, Vec<u8>>) -> Self {
+        Self {
+            manifest: Manifest {
+                reader,
+                atr: hex::encode_upper(atr),
+                read_at: Local::now().naive_local(),
+                files: files
+                    .iter()
+                    .map(|(file, bytes)| (file.file_name().to_string(), sha256(bytes)))
+                    .collect(),
+            },
+            files,
+        }
+    }
+
+    /// Writes the dump into the directory, creating it if needed. Returns the paths written.
+    /// The files hold personal data of the vehicle owner, so only the user may read them.
+    pub fn save(&self, directory: &Path) -> Result<Vec<PathBuf>, DumpError> {
+        fs::create_dir_all(directory).map_err(|err| DumpError::Io(directory.to_owned(), err))?;
+        let mut written = Vec::new();
+        for file in File::ALL {
+            let Some(bytes) = self.files.get(&file) else {
+                continue;
+            };
+            written.push(write(&directory.join(file.file_name()), bytes)?);
+        }
+        let manifest = serde_json::to_string_pretty(&self.manifest)?;
+        written.push(write(&directory.join(MANIFEST_FILE), manifest)?);
+        Ok(written)
+    }
+
+    /// Loads a dump written by [`CardDump::save`], checking every file against the manifest.
+    pub fn load(directory: &Path) -> Result<Self, DumpError> {
+        let manifest: Manifest = serde_json::from_slice(&read(&directory.join(MANIFEST_FILE))?)?;
+        let mut files = HashMap::new();
+        for file in File::ALL {
+            let name = file.file_name();
+            let Some(hash) = manifest.files.get(name) else {
+                return Err(DumpError::Missing(name.to_string()));
+            };
+            let bytes = read(&directory.join(name))?;
+            if !sha256(&bytes).eq_ignore_ascii_case(hash) {
+                return Err(DumpError::Modified(name));
+            }
+            files.insert(file, bytes);
+        }
+        Ok(Self { manifest, files })
+    }
+
+    /// Parses the registration files, the same way a card that is read is.
+    pub fn contents(&self) -> CardContents {
+        CardContents::new(&parse_files(&self.files))
+    }
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, DumpError> {
+    fs::read(path).map_err(|err| match (err.kind(), path.file_name()) {
+        (io::ErrorKind::NotFound, Some(name)) => {
+            DumpError::Missing(name.to_string_lossy().into_owned())
+        }
+        _ => DumpError::Io(path.to_owned(), err),
+    })
+}
+
+fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<PathBuf, DumpError> {
+    write_private(path, contents).map_err(|err| DumpError::Io(path.to_owned(), err))?;
+    Ok(path.to_owned())
+}
@@ -1,114 +1,556 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
 use iced::{
-    widget::{button, checkbox, column, pick_list, row, text, text_input},
-    Alignment, Application, Command, Element,
+    clipboard,
+    widget::{button, checkbox, column, pick_list, row, text, text_input, Column},
+    Alignment, Application, Command, Element, Length, Subscription,
 };
-use log::error;
+use log::{error, info, LevelFilter};
+use rfd::AsyncFileDialog;
 
-use crate::reader::Reader;
+use crate::{
+    job::{self, Job, Outcome, Update},
+    parsing::CardContents,
+    queue::{self, QueueError, QueuedUpload, UploadQueue},
+    reader::{CardState, Progress, Reader, RecentCards},
+    server::{Connection, Server, ServerError},
+    settings::Settings,
+    viewer,
+    watcher::{self, Event},
+};
+
+static LOG_LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
 
 pub struct VehikularSettings {
-    address: String,
-    auto_upload: bool,
-    auto_open: bool,
+    settings: Settings,
+    /// Why the settings were not saved.
+    settings_problem: Option<String>,
+    testing_connection: bool,
     reader: Reader,
+    /// The connected readers and the state of their card, as last reported by the watcher.
+    connected_readers: Vec<(String, CardState)>,
+    /// The reader the buttons read from.
     selected_reader: Option<String>,
+    /// What every reader that was used is doing, by its name. Each reader reads a card of its
+    /// own at the same time as the others.
+    reader_statuses: BTreeMap<String, ReaderStatus>,
+    /// The cards that were read on their own lately, shared with the jobs reading them.
+    recent_cards: Arc<Mutex<RecentCards>>,
     status_message: Option<String>,
+    next_job_id: u64,
+    /// Cards that were read but could not be uploaded yet.
+    queue: UploadQueue,
+    last_read: Option<CardContents>,
+    /// Show the last read card instead of the settings.
+    viewing: bool,
+}
+
+/// What a reader is doing, or how its last card went.
+#[derive(Default)]
+struct ReaderStatus {
+    running: Option<RunningJob>,
+    message: Option<String>,
+}
+
+/// The job that is reading a card right now.
+struct RunningJob {
+    job: Job,
+    then: AfterRead,
+    progress: Option<Progress>,
+}
+
+/// What to do with a card once it has been read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AfterRead {
+    Nothing,
+    OpenPage,
+    ShowLocally,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     AddressChanged(String),
+    UsernameChanged(String),
+    PasswordChanged(String),
+    PinnedCertificateChanged(String),
+    ChooseCaCertificate,
+    /// The chosen file, or none to only trust the authorities of the system.
+    CaCertificateChosen(Option<PathBuf>),
+    TestConnection,
+    ConnectionTested(Result<(), String>),
     ChangeReader(String),
     ToggleAutoUpload,
     ToggleAutoOpen,
+    ToggleAllReaders,
+    DedupeSecondsChanged(String),
+    LogLevelChanged(LevelFilter),
+    ToggleLogToFile,
+    ChooseLogDirectory,
+    LogDirectoryChosen(Option<PathBuf>),
     UploadCard,
     ViewCardLocal,
     ViewCardWeb,
-    RefreshReaders,
+    /// Whether the vehicle is on the server, or why that could not be found out.
+    WebPageChecked(String, Result<bool, String>),
+    Watcher(Event),
+    Job(u64, Update),
+    /// Stops reading the card in the reader.
+    CancelJob(String),
+    CopyJson,
+    SaveJson,
+    SaveJsonTo(Option<PathBuf>),
+    CloseViewer,
+    QueueTick,
+    /// How the upload of a queued card went, and whether trying again may help if it failed.
+    QueuedUploadFinished(u64, Result<(), (String, bool)>),
+    RetryQueued(u64),
+    DiscardQueued(u64),
 }
 
 impl Application for VehikularSettings {
     type Message = Message;
     type Executor = iced::executor::Default;
     type Theme = iced::Theme;
-    type Flags = ();
+    /// The saved settings and, if they could not be loaded, why.
+    type Flags = (Settings, Option<String>);
 
     fn view(&self) -> Element<Message> {
+        if let Some(contents) = self.last_read.as_ref().filter(|_| self.viewing) {
+            return viewer::view(contents);
+        }
+
         let connection_text = text("Address");
-        let connection_edit =
-            text_input("e.g. localhost:8000", &self.address).on_input(Message::AddressChanged);
+        let connection_edit = text_input(
+            "e.g. localhost:8000 or https://vehikular.example.com",
+            &self.settings.address,
+        )
+        .on_input(Message::AddressChanged);
         let connection = row![connection_text, connection_edit]
             .spacing(5)
             .align_items(Alignment::Center);
 
+        let credentials = &self.settings.credentials;
+        let username = text_input(
+            "Username, if the server asks for one",
+            &credentials.username,
+        )
+        .on_input(Message::UsernameChanged);
+        let password = text_input("Password", &credentials.password)
+            .on_input(Message::PasswordChanged)
+            .password();
+        let mut test_connection = button("Test connection");
+        if !self.testing_connection {
+            test_connection = test_connection.on_press(Message::TestConnection);
+        }
+        let login = row![username, password, test_connection]
+            .spacing(5)
+            .align_items(Alignment::Center);
+
+        let ca_certificate = text(match &self.settings.ca_certificate {
+            Some(path) => path.display().to_string(),
+            None => "Only the system ones".to_string(),
+        });
+        let pinned_certificate = text_input(
+            "SHA-256 fingerprint, e.g. for a self-signed certificate",
+            self.settings
+                .pinned_certificate
+                .as_deref()
+                .unwrap_or_default(),
+        )
+        .on_input(Message::PinnedCertificateChanged);
+        let ca_certificate = row![
+            text("Trusted certificate authority"),
+            ca_certificate,
+            button("Choose file").on_press(Message::ChooseCaCertificate),
+            button("Clear").on_press(Message::CaCertificateChosen(None)),
+        ]
+        .spacing(5)
+        .align_items(Alignment::Center);
+        let pinned_certificate = row![text("Pinned certificate"), pinned_certificate]
+            .spacing(5)
+            .align_items(Alignment::Center);
+        let tls = column![ca_certificate, pinned_certificate].spacing(5);
+
         let reader_text = text("Using reader ");
         let reader_dropdown = pick_list(
-            self.reader.get_readers(),
+            self.connected_readers
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>(),
             self.selected_reader.clone(),
             Message::ChangeReader,
         );
-        let reader_refresh = button("Refresh readers").on_press(Message::RefreshReaders);
-        let readers = row![reader_text, reader_dropdown, reader_refresh]
+        let readers = row![reader_text, reader_dropdown]
             .spacing(5)
             .align_items(Alignment::Center);
 
-        // let auto_text = text("When a card is inserted");
-        // let auto_upload = checkbox(
-        //     "Automatically upload vehicle data",
-        //     self.auto_upload,
-        //     |_| Message::ToggleAutoUpload,
-        // );
-        // let auto_open = checkbox("Open vehicle webpage", self.auto_open, |_| {
-        //     Message::ToggleAutoOpen
-        // });
-        // let auto = column![auto_text, auto_upload, auto_open].spacing(5);
-
-        let manual_upload = button("Upload card content").on_press(Message::UploadCard);
-        //let view_local = button("View data locally").on_press(Message::ViewCardLocal);
-        //let view_web = button("View data on the web").on_press(Message::ViewCardWeb);
-
-        let actions = row![manual_upload].spacing(5);
-
-        let message = match &self.status_message {
-            Some(message) => message,
-            None => "",
+        let auto_text = text("When a card is inserted");
+        let auto_upload = checkbox(
+            "Automatically upload vehicle data",
+            self.settings.auto_upload,
+            |_| Message::ToggleAutoUpload,
+        );
+        let auto_open = checkbox("Open vehicle webpage", self.settings.auto_open, |_| {
+            Message::ToggleAutoOpen
+        });
+        let all_readers = checkbox(
+            "Read cards inserted into any reader, not only the chosen one",
+            self.settings.all_readers,
+            |_| Message::ToggleAllReaders,
+        );
+        let dedupe_seconds = text_input("0", &self.settings.dedupe_seconds.to_string())
+            .on_input(Message::DedupeSecondsChanged)
+            .width(Length::Fixed(60.0));
+        let dedupe = row![
+            text("Skip a card that was read within the last"),
+            dedupe_seconds,
+            text("seconds"),
+        ]
+        .spacing(5)
+        .align_items(Alignment::Center);
+        let auto = column![auto_text, auto_upload, auto_open, all_readers, dedupe].spacing(5);
+
+        let log = &self.settings.log;
+        let log_level = pick_list(&LOG_LEVELS[..], Some(log.level), Message::LogLevelChanged);
+        let log_to_file = checkbox("Write log files to", log.to_file, |_| {
+            Message::ToggleLogToFile
+        });
+        let log_directory = text(match &log.directory {
+            Some(directory) => directory.display().to_string(),
+            None => "the working directory".to_string(),
+        });
+        let choose_log_directory = button("Choose folder").on_press(Message::ChooseLogDirectory);
+        let logging = row![
+            text("Log level"),
+            log_level,
+            log_to_file,
+            log_directory,
+            choose_log_directory,
+            text("(applies on the next start)"),
+        ]
+        .spacing(5)
+        .align_items(Alignment::Center);
+
+        let mut manual_upload = button("Upload card content");
+        let mut view_local = button("View data locally");
+        let selected_busy = self
+            .selected_reader
+            .as_ref()
+            .is_some_and(|reader| self.is_busy(reader));
+        if !selected_busy {
+            manual_upload = manual_upload.on_press(Message::UploadCard);
+            view_local = view_local.on_press(Message::ViewCardLocal);
+        }
+        let mut view_web = button("View data on the web");
+        if self.last_read.is_some() {
+            view_web = view_web.on_press(Message::ViewCardWeb);
+        }
+
+        let actions = row![manual_upload, view_local, view_web].spacing(5);
+
+        let status_message = text(self.status_message.as_deref().unwrap_or_default());
+
+        let reader_statuses = self
+            .connected_readers
+            .iter()
+            .fold(Column::new().spacing(5), |statuses, (reader, card)| {
+                statuses.push(self.reader_status(reader, *card))
+            });
+
+        let settings_problem = text(self.settings_problem.as_deref().unwrap_or_default());
+
+        let pending = match self.queue.items() {
+            [] => Column::new(),
+            items => items.iter().fold(
+                column![text(format!("Waiting to be uploaded: {}", items.len()))].spacing(5),
+                |pending, item| pending.push(queued_upload(item)),
+            ),
         };
-        let status_message = text(message);
 
-        column![connection, readers, actions, status_message]
-            .padding(10)
-            .spacing(10)
-            .into()
+        column![
+            connection,
+            login,
+            tls,
+            readers,
+            auto,
+            logging,
+            settings_problem,
+            actions,
+            status_message,
+            reader_statuses,
+            pending
+        ]
+        .padding(10)
+        .spacing(10)
+        .into()
     }
 
     fn update(&mut self, message: Message) -> iced::Command<Message> {
         match message {
-            Message::ToggleAutoUpload => todo!(),
-            Message::ToggleAutoOpen => todo!(),
+            Message::ToggleAutoUpload => {
+                self.settings.auto_upload = !self.settings.auto_upload;
+                self.save_settings();
+            }
+            Message::ToggleAutoOpen => {
+                self.settings.auto_open = !self.settings.auto_open;
+                self.save_settings();
+            }
+            Message::ToggleAllReaders => {
+                self.settings.all_readers = !self.settings.all_readers;
+                self.save_settings();
+            }
+            Message::DedupeSecondsChanged(seconds) => {
+                let seconds = seconds.trim();
+                if seconds.is_empty() {
+                    self.settings.dedupe_seconds = 0;
+                } else if let Ok(seconds) = seconds.parse() {
+                    self.settings.dedupe_seconds = seconds;
+                } else {
+                    return Command::none();
+                }
+                if let Ok(mut recent) = self.recent_cards.lock() {
+                    recent.set_window(self.settings.dedupe_window());
+                }
+                self.save_settings();
+            }
             Message::UploadCard => {
-                if let Some(reader) = &self.selected_reader {
-                    match self.reader.process_reader(reader, &self.address) {
-                        Ok(_) => self.status_message = Some("Finished uploading".to_string()),
-                        Err(err) => {
-                            error!("An error occured whilst processing the card: {err}");
-                            self.status_message = Some(format!("An error occured: {err}"));
+                if let Some(reader) = self.selected_reader.clone() {
+                    self.start_job(&reader, true, AfterRead::Nothing, false);
+                }
+            }
+            Message::ViewCardLocal => {
+                if let Some(reader) = self.selected_reader.clone() {
+                    self.start_job(&reader, false, AfterRead::ShowLocally, false);
+                }
+            }
+            Message::ViewCardWeb => {
+                let Some(contents) = &self.last_read else {
+                    self.status_message = Some("Read a card first.".to_string());
+                    return Command::none();
+                };
+                let registration_number = contents.registration.registration_number.clone();
+                let Some(connection) = self.connection() else {
+                    return Command::none();
+                };
+                self.status_message =
+                    Some(format!("Looking for {registration_number} on the server"));
+                return Command::perform(
+                    job::run_blocking({
+                        let registration_number = registration_number.clone();
+                        move || {
+                            Server::connect(&connection)
+                                .and_then(|server| server.is_on_server(&registration_number))
+                                .map_err(|err| err.to_string())
                         }
+                    }),
+                    move |result| Message::WebPageChecked(registration_number, result),
+                );
+            }
+            Message::WebPageChecked(registration_number, result) => match result {
+                Ok(true) => {
+                    self.status_message = None;
+                    self.open_page(&registration_number);
+                }
+                Ok(false) => {
+                    self.status_message = Some(format!(
+                        "{registration_number} is not on the server yet. Upload the card first."
+                    ));
+                }
+                Err(err) => {
+                    error!("Could not look for {registration_number} on the server: {err}");
+                    self.status_message = Some(err);
+                }
+            },
+            Message::AddressChanged(address) => {
+                self.settings.address = address;
+                self.save_settings();
+            }
+            Message::UsernameChanged(username) => {
+                self.settings.credentials.username = username;
+                self.save_settings();
+            }
+            Message::PasswordChanged(password) => {
+                self.settings.credentials.password = password;
+                self.save_settings();
+            }
+            Message::PinnedCertificateChanged(fingerprint) => {
+                self.settings.pinned_certificate =
+                    Some(fingerprint).filter(|fingerprint| !fingerprint.trim().is_empty());
+                self.save_settings();
+            }
+            Message::ChooseCaCertificate => {
+                return Command::perform(
+                    async {
+                        AsyncFileDialog::new()
+                            .add_filter("PEM certificate", &["pem", "crt"])
+                            .pick_file()
+                            .await
+                            .map(|file| file.path().to_path_buf())
+                    },
+                    Message::CaCertificateChosen,
+                );
+            }
+            Message::CaCertificateChosen(path) => {
+                self.settings.ca_certificate = path;
+                self.save_settings();
+            }
+            Message::TestConnection => {
+                let Some(connection) = self.connection() else {
+                    return Command::none();
+                };
+                self.testing_connection = true;
+                self.status_message = Some("Contacting the server".to_string());
+                return Command::perform(
+                    job::run_blocking(move || {
+                        Server::connect(&connection)
+                            .and_then(|server| server.test_connection())
+                            .map_err(|err| err.to_string())
+                    }),
+                    Message::ConnectionTested,
+                );
+            }
+            Message::ConnectionTested(result) => {
+                self.testing_connection = false;
+                self.status_message = Some(match result {
+                    Ok(()) => "The server is reachable.".to_string(),
+                    Err(err) => {
+                        error!("The connection test failed: {err}");
+                        err
+                    }
+                });
+            }
+            Message::ChangeReader(reader) => {
+                self.settings.preferred_reader = Some(reader.clone());
+                self.selected_reader = Some(reader);
+                self.save_settings();
+            }
+            Message::LogLevelChanged(level) => {
+                self.settings.log.level = level;
+                self.save_settings();
+            }
+            Message::ToggleLogToFile => {
+                self.settings.log.to_file = !self.settings.log.to_file;
+                self.save_settings();
+            }
+            Message::ChooseLogDirectory => {
+                return Command::perform(
+                    async {
+                        AsyncFileDialog::new()
+                            .pick_folder()
+                            .await
+                            .map(|folder| folder.path().to_path_buf())
+                    },
+                    Message::LogDirectoryChosen,
+                );
+            }
+            Message::LogDirectoryChosen(Some(directory)) => {
+                self.settings.log.directory = Some(directory);
+                self.save_settings();
+            }
+            Message::LogDirectoryChosen(None) => {}
+            Message::Watcher(Event::ReadersChanged(readers)) => self.readers_changed(readers),
+            Message::Watcher(Event::CardInserted(reader)) => self.card_inserted(reader),
+            Message::Watcher(Event::Stopped(err)) => {
+                self.status_message = Some(format!("Stopped watching for cards: {err}"));
+            }
+            Message::Job(id, update) => self.job_update(id, update),
+            Message::CancelJob(reader) => {
+                if let Some(status) = self.reader_statuses.get_mut(&reader) {
+                    if let Some(running) = status.running.take() {
+                        running.job.cancel();
+                        status.message = Some("Cancelled".to_string());
                     }
                 }
             }
-            Message::ViewCardLocal => todo!(),
-            Message::ViewCardWeb => todo!(),
-            Message::AddressChanged(address) => self.address = address,
-            Message::ChangeReader(reader) => self.selected_reader = Some(reader),
-            Message::RefreshReaders => match self.reader.update_readers() {
-                Ok(_) => {},
-                Err(err) => self.status_message = Some(format!("Could not update readers: {err}")),
-            },
+            Message::CopyJson => {
+                if let Some(json) = self.last_read_json() {
+                    return clipboard::write(json);
+                }
+            }
+            Message::SaveJson => {
+                if let Some(contents) = &self.last_read {
+                    let file_name = format!("{}.json", contents.registration.registration_number);
+                    return Command::perform(
+                        async move {
+                            AsyncFileDialog::new()
+                                .set_file_name(&file_name)
+                                .add_filter("JSON", &["json"])
+                                .save_file()
+                                .await
+                                .map(|file| file.path().to_path_buf())
+                        },
+                        Message::SaveJsonTo,
+                    );
+                }
+            }
+            Message::SaveJsonTo(Some(path)) => {
+                if let Some(json) = self.last_read_json() {
+                    self.status_message = Some(match fs::write(&path, json) {
+                        Ok(()) => format!("Saved to {}", path.display()),
+                        Err(err) => {
+                            error!("Could not save to {}: {err}", path.display());
+                            format!("Could not save to {}: {err}", path.display())
+                        }
+                    });
+                }
+            }
+            Message::SaveJsonTo(None) => {}
+            Message::CloseViewer => self.viewing = false,
+            Message::QueueTick => return self.upload_due_queued(),
+            Message::QueuedUploadFinished(id, result) => {
+                if let Err((err, _)) = &result {
+                    error!("The queued upload {id} failed: {err}");
+                }
+                let result = self.queue.finished(id, result);
+                self.report_queue_error(result);
+                return self.upload_due_queued();
+            }
+            Message::RetryQueued(id) => {
+                let result = self.queue.retry_now(id);
+                self.report_queue_error(result);
+                return self.upload_due_queued();
+            }
+            Message::DiscardQueued(id) => {
+                let result = self.queue.discard(id);
+                self.report_queue_error(result);
+            }
         };
 
         Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        let mut subscriptions = vec![watcher::watch().map(Message::Watcher)];
+        for running in self
+            .reader_statuses
+            .values()
+            .filter_map(|status| status.running.as_ref())
+        {
+            subscriptions.push(
+                running
+                    .job
+                    .run()
+                    .map(|(id, update)| Message::Job(id, update)),
+            );
+        }
+        if !self.queue.items().is_empty() {
+            subscriptions.push(queue::ticks().map(|()| Message::QueueTick));
+        }
+        Subscription::batch(subscriptions)
+    }
+
     fn title(&self) -> String {
         "Vehikular Desktop".to_string()
     }
@@ -117,19 +559,375 @@ impl Application for VehikularSettings {
         iced::Theme::Dark
     }
 
-    fn new(_flags: Self::Flags) -> (Self, iced::Command<Self::Message>) {
+    fn new((settings, load_error): Self::Flags) -> (Self, iced::Command<Self::Message>) {
         let mut reader = Reader::new().expect("Could not create reader.");
         reader.update_readers().expect("Could not read readers");
+        // A preferred reader that is not connected would keep cards in the others from being read.
+        let selected_reader = settings
+            .preferred_reader
+            .clone()
+            .filter(|preferred| reader.get_readers().contains(preferred));
+        let (queue, queue_error) = match UploadQueue::load() {
+            Ok(queue) => (queue, None),
+            Err(err) => {
+                error!("Could not load the upload queue: {err}");
+                (UploadQueue::default(), Some(err.to_string()))
+            }
+        };
         (
             VehikularSettings {
+                settings,
+                settings_problem: None,
+                testing_connection: false,
+                connected_readers: reader.card_states(),
                 reader,
-                auto_upload: false,
-                auto_open: false,
-                address: String::new(),
-                selected_reader: None,
-                status_message: None,
+                selected_reader,
+                status_message: load_error
+                    .map(|err| format!("Could not load the settings, using the defaults: {err}"))
+                    .or(queue_error),
+                reader_statuses: BTreeMap::new(),
+                recent_cards: Arc::new(Mutex::new(RecentCards::new(settings.dedupe_window()))),
+                next_job_id: 0,
+                queue,
+                last_read: None,
+                viewing: false,
             },
             Command::none(),
         )
     }
 }
+
+impl VehikularSettings {
+    /// Shows the readers as they are now. A selected reader that was disconnected is no longer
+    /// selected, and the preferred one is selected again once it is connected.
+    fn readers_changed(&mut self, readers: Vec<(String, CardState)>) {
+        let is_connected = |name: &String| readers.iter().any(|(reader, _)| reader == name);
+        match &self.selected_reader {
+            Some(selected) if !is_connected(selected) => {
+                info!("The selected reader {selected} was disconnected.");
+                self.status_message = Some(format!(
+                    "{selected} was disconnected. Choose another reader or connect it again."
+                ));
+                self.selected_reader = None;
+            }
+            Some(_) => {}
+            None => {
+                self.selected_reader = self.settings.preferred_reader.clone().filter(is_connected);
+            }
+        }
+        self.connected_readers = readers;
+    }
+
+    /// Reads a freshly inserted card, then uploads it and opens its page if that is turned on.
+    /// Cards in other readers than the selected one are left alone, unless all readers are used.
+    fn card_inserted(&mut self, reader: String) {
+        match &self.selected_reader {
+            Some(selected) if *selected != reader && !self.settings.all_readers => return,
+            Some(_) => {}
+            None => self.selected_reader = Some(reader.clone()),
+        }
+        let then = if self.settings.auto_open {
+            AfterRead::OpenPage
+        } else {
+            AfterRead::Nothing
+        };
+        self.start_job(&reader, self.settings.auto_upload, then, true);
+    }
+
+    /// Whether a card is being read in the reader.
+    fn is_busy(&self, reader: &str) -> bool {
+        self.reader_statuses
+            .get(reader)
+            .is_some_and(|status| status.running.is_some())
+    }
+
+    /// Starts reading the card in the background, unless a card is being read in that reader
+    /// already. Cards that were inserted, instead of read with a button, are left alone if they
+    /// were read a moment ago.
+    fn start_job(&mut self, reader: &str, upload: bool, then: AfterRead, inserted: bool) {
+        if self.is_busy(reader) {
+            return;
+        }
+        let connection = if upload {
+            let Some(connection) = self.connection() else {
+                return;
+            };
+            Some(connection)
+        } else {
+            None
+        };
+        // The reader only knows about a new card once it has looked at the readers again.
+        let card = self
+            .reader
+            .update_readers()
+            .and_then(|_| self.reader.find_card(reader));
+        let status = self.reader_statuses.entry(reader.to_string()).or_default();
+        let card = match card {
+            Ok(card) => card,
+            Err(err) => {
+                error!("An error occured whilst processing the card: {err}");
+                status.message = Some(format!("An error occured: {err}"));
+                return;
+            }
+        };
+
+        self.next_job_id += 1;
+        status.running = Some(RunningJob {
+            job: Job::new(
+                self.next_job_id,
+                card,
+                connection,
+                Some(self.recent_cards.clone()).filter(|_| inserted),
+            ),
+            then,
+            progress: None,
+        });
+    }
+
+    fn job_update(&mut self, id: u64, update: Update) {
+        // Updates of a cancelled job can still be on their way.
+        let Some((reader, status)) = self.reader_statuses.iter_mut().find(|(_, status)| {
+            status
+                .running
+                .as_ref()
+                .is_some_and(|running| running.job.id == id)
+        }) else {
+            return;
+        };
+        let reader = reader.clone();
+        let Some(running) = status.running.as_mut() else {
+            return;
+        };
+        match update {
+            Update::Progress(progress) => running.progress = Some(progress),
+            Update::Finished(result) => {
+                let then = running.then;
+                status.running = None;
+                match result {
+                    Ok((contents, outcome)) => self.job_finished(&reader, contents, outcome, then),
+                    Err(err) => status.message = Some(format!("An error occured: {err}")),
+                }
+            }
+            Update::AlreadyRead {
+                registration_number,
+                ago,
+            } => {
+                status.running = None;
+                status.message = Some(format!(
+                    "Skipped {registration_number}, it was read {} seconds ago.",
+                    ago.as_secs()
+                ));
+            }
+            Update::UploadFailed {
+                contents,
+                error,
+                temporary,
+            } => {
+                status.running = None;
+                let registration_number = &contents.registration.registration_number;
+                let queued = self.queue.push(
+                    contents.registration.clone(),
+                    Some(reader),
+                    error.clone(),
+                    temporary,
+                );
+                status.message = Some(match queued {
+                    Ok(()) if temporary => format!(
+                        "Could not upload {registration_number}: {error} It is queued and will be \
+                         uploaded once the server can be reached."
+                    ),
+                    Ok(()) => format!(
+                        "The server refused {registration_number}: {error} It is kept in the \
+                         queue until you retry or discard it."
+                    ),
+                    Err(err) => {
+                        error!("Could not queue {registration_number}: {err}");
+                        format!("Could not upload {registration_number}, nor queue it: {err}")
+                    }
+                });
+                self.last_read = Some(contents);
+            }
+        }
+    }
+
+    /// Starts uploading the due cards of the queue, if the settings allow it. Every reader
+    /// uploads one card at a time.
+    fn upload_due_queued(&mut self) -> Command<Message> {
+        if self.settings.validate().is_err() {
+            return Command::none();
+        }
+        let mut uploads = Vec::new();
+        while let Some(item) = self.queue.next_due() {
+            uploads.push(upload_queued(self.settings.connection(), item));
+        }
+        Command::batch(uploads)
+    }
+
+    fn report_queue_error(&mut self, result: Result<(), QueueError>) {
+        if let Err(err) = result {
+            error!("Could not save the upload queue: {err}");
+            self.status_message = Some(err.to_string());
+        }
+    }
+
+    fn job_finished(
+        &mut self,
+        reader: &str,
+        contents: CardContents,
+        outcome: Outcome,
+        then: AfterRead,
+    ) {
+        let registration_number = &contents.registration.registration_number;
+        self.reader_statuses
+            .entry(reader.to_string())
+            .or_default()
+            .message = Some(match outcome {
+            Outcome::Read => format!("Read {registration_number}"),
+            Outcome::Uploaded => format!("Uploaded {registration_number}"),
+            Outcome::AlreadyUploaded => {
+                format!("Read {registration_number}, it is already on the server")
+            }
+        });
+        match then {
+            AfterRead::Nothing => {}
+            AfterRead::OpenPage => self.open_page(registration_number),
+            AfterRead::ShowLocally => self.viewing = true,
+        }
+        self.last_read = Some(contents);
+    }
+
+    /// A line of the reader panel.
+    fn reader_status(&self, reader: &str, card: CardState) -> Element<'_, Message> {
+        let status = self.reader_statuses.get(reader);
+        let state = match status {
+            Some(ReaderStatus {
+                running: Some(running),
+                ..
+            }) => match running.progress {
+                Some(progress) => format!("{progress}..."),
+                None => "Starting...".to_string(),
+            },
+            Some(ReaderStatus {
+                message: Some(message),
+                ..
+            }) => message.clone(),
+            _ => String::new(),
+        };
+        let waiting = self
+            .queue
+            .items()
+            .iter()
+            .filter(|item| item.reader.as_deref() == Some(reader))
+            .count();
+        let mut line = row![text(reader), text(format!("{card}.")), text(state)]
+            .spacing(5)
+            .align_items(Alignment::Center);
+        if waiting > 0 {
+            line = line.push(text(format!("({waiting} waiting to be uploaded)")));
+        }
+        if self.is_busy(reader) {
+            line = line.push(button("Cancel").on_press(Message::CancelJob(reader.to_string())));
+        }
+        line.into()
+    }
+
+    fn open_page(&mut self, registration_number: &str) {
+        let url = self
+            .settings
+            .connection()
+            .registration_url(registration_number);
+        info!("Opening {url}");
+        if let Err(err) = open::that(&url) {
+            error!("Could not open {url}: {err}");
+            self.status_message = Some(format!("Could not open the vehicle webpage: {err}"));
+        }
+    }
+
+    /// How to reach the server, as long as the settings are valid.
+    fn connection(&mut self) -> Option<Connection> {
+        match self.settings.validate() {
+            Ok(()) => Some(self.settings.connection()),
+            Err(err) => {
+                self.status_message = Some(err.to_string());
+                None
+            }
+        }
+    }
+
+    /// Saves the settings, and shows why they could not be saved or why the server can not be
+    /// reached with them.
+    fn save_settings(&mut self) {
+        self.settings_problem = match self.settings.save() {
+            Ok(()) => self.settings.validate().err().map(|err| err.to_string()),
+            Err(err) => {
+                error!("Could not save the settings: {err}");
+                Some(err.to_string())
+            }
+        };
+    }
+
+    fn last_read_json(&mut self) -> Option<String> {
+        let contents = self.last_read.as_ref()?;
+        match contents.to_json() {
+            Ok(json) => Some(json),
+            Err(err) => {
+                error!("Could not turn the card contents into JSON: {err}");
+                self.status_message = Some(format!("Could not turn the card into JSON: {err}"));
+                None
+            }
+        }
+    }
+}
+
+/// Uploads a card of the queue in the background.
+fn upload_queued(connection: Connection, item: QueuedUpload) -> Command<Message> {
+    info!("Uploading queued {}", item.registration.registration_number);
+    let id = item.id;
+    Command::perform(
+        job::run_blocking(move || {
+            let uploaded =
+                Server::connect(&connection).and_then(|server| server.upload(&item.registration));
+            Ok(match uploaded {
+                // Someone else uploaded it in the meantime.
+                Ok(()) | Err(ServerError::AlreadyExists) => Ok(()),
+                Err(err) => Err((err.to_string(), err.is_temporary())),
+            })
+        }),
+        move |result| {
+            Message::QueuedUploadFinished(id, result.unwrap_or_else(|err| Err((err, true))))
+        },
+    )
+}
+
+/// A line of the upload queue.
+fn queued_upload(item: &QueuedUpload) -> Element<'_, Message> {
+    let state = match item.retry_at {
+        _ if item.uploading => "Uploading...".to_string(),
+        Some(retry_at) => format!("Trying again at {}.", retry_at.format("%H:%M:%S")),
+        None => "Not tried again on its own.".to_string(),
+    };
+    let mut retry = button("Retry");
+    let mut discard = button("Discard");
+    if !item.uploading {
+        retry = retry.on_press(Message::RetryQueued(item.id));
+        discard = discard.on_press(Message::DiscardQueued(item.id));
+    }
+    let reader = match &item.reader {
+        Some(reader) => format!(" with {reader}"),
+        None => String::new(),
+    };
+    row![
+        text(format!(
+            "{}, read at {}{reader}, {} attempts: {} {state}",
+            item.registration.registration_number,
+            item.queued_at.format("%Y-%m-%d %H:%M"),
+            item.attempts,
+            item.last_error,
+        )),
+        retry,
+        discard,
+    ]
+    .spacing(5)
+    .align_items(Alignment::Center)
+    .into()
+}
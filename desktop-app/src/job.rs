@@ -0,0 +1,219 @@
+use std::{
+    any::TypeId,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use iced::{
+    futures::{
+        channel::{
+            mpsc::{self, UnboundedReceiver, UnboundedSender},
+            oneshot,
+        },
+        future, StreamExt,
+    },
+    subscription, Subscription,
+};
+use log::{error, info};
+
+use crate::{
+    parsing::CardContents,
+    reader::{self, InsertedCard, Progress, RecentCards},
+    server::{Connection, Server, ServerError},
+};
+
+/// What a job reports back to the GUI.
+#[derive(Debug, Clone)]
+pub enum Update {
+    Progress(Progress),
+    Finished(Result<(CardContents, Outcome), String>),
+    /// The card was read a moment ago already, so it was left alone this time.
+    AlreadyRead {
+        registration_number: String,
+        ago: Duration,
+    },
+    /// The card was read, but not uploaded. Temporary errors may go away when trying again.
+    UploadFailed {
+        contents: CardContents,
+        error: String,
+        temporary: bool,
+    },
+}
+
+/// What became of a card that was read successfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// There was no connection, so the card was only read.
+    Read,
+    Uploaded,
+    /// The server had the registration already, so there was nothing left to upload.
+    AlreadyUploaded,
+}
+
+/// Reads a card, and uploads it if there is a connection, on a thread of its own so the window
+/// keeps responding.
+#[derive(Clone)]
+pub struct Job {
+    pub id: u64,
+    card: InsertedCard,
+    connection: Option<Connection>,
+    /// Set for cards that were read on their own, which are left alone if they were read a
+    /// moment ago already.
+    recent: Option<Arc<Mutex<RecentCards>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Job {
+    pub fn new(
+        id: u64,
+        card: InsertedCard,
+        connection: Option<Connection>,
+        recent: Option<Arc<Mutex<RecentCards>>>,
+    ) -> Self {
+        Job {
+            id,
+            card,
+            connection,
+            recent,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Stops the job before its next step. A step that already started, like a running upload,
+    /// is not interrupted, but its result is thrown away.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Runs the job and reports its progress, tagged with the id of the job.
+    pub fn run(&self) -> Subscription<(u64, Update)> {
+        struct Worker;
+
+        let id = self.id;
+        subscription::unfold(
+            (TypeId::of::<Worker>(), id),
+            State::Ready(self.clone()),
+            move |state| async move {
+                let mut receiver = match state {
+                    State::Ready(job) => {
+                        let (sender, receiver) = mpsc::unbounded();
+                        thread::spawn(move || job.work(&sender));
+                        receiver
+                    }
+                    State::Running(receiver) => receiver,
+                    State::Done => future::pending().await,
+                };
+                match receiver.next().await {
+                    Some(
+                        update @ (Update::Finished(_)
+                        | Update::AlreadyRead { .. }
+                        | Update::UploadFailed { .. }),
+                    ) => ((id, update), State::Done),
+                    Some(update) => ((id, update), State::Running(receiver)),
+                    // The job was cancelled and stopped without a result.
+                    None => future::pending().await,
+                }
+            },
+        )
+    }
+
+    fn work(&self, sender: &UnboundedSender<Update>) {
+        let mut progress = |step: Progress| {
+            if self.cancelled.load(Ordering::Relaxed) {
+                return false;
+            }
+            info!("{step}");
+            // Once the GUI stops listening there is no point in going on.
+            sender.unbounded_send(Update::Progress(step)).is_ok()
+        };
+
+        let update = match self.card.read(&mut progress) {
+            Ok(contents) => match self.already_read(&contents) {
+                Some(ago) => {
+                    info!(
+                        "Leaving {} alone, it was read {} seconds ago.",
+                        contents.registration.registration_number,
+                        ago.as_secs()
+                    );
+                    Update::AlreadyRead {
+                        registration_number: contents.registration.registration_number,
+                        ago,
+                    }
+                }
+                None => self.upload_if_connected(contents, &mut progress),
+            },
+            Err(err) => Update::Finished(Err(err.to_string())),
+        };
+
+        match &update {
+            Update::Finished(Err(err)) | Update::UploadFailed { error: err, .. } => {
+                error!("An error occured whilst processing the card: {err}");
+            }
+            _ => {}
+        }
+        if !self.cancelled.load(Ordering::Relaxed) {
+            let _ = sender.unbounded_send(update);
+        }
+    }
+
+    /// How long ago the card was read before, if that was within the dedupe window.
+    fn already_read(&self, contents: &CardContents) -> Option<Duration> {
+        let recent = self.recent.as_ref()?;
+        recent
+            .lock()
+            .ok()?
+            .note(&contents.registration, Instant::now())
+    }
+
+    fn upload_if_connected(
+        &self,
+        contents: CardContents,
+        progress: &mut impl FnMut(Progress) -> bool,
+    ) -> Update {
+        match &self.connection {
+            Some(_) if !progress(Progress::Uploading) => {
+                Update::Finished(Err(reader::Error::Cancelled.to_string()))
+            }
+            Some(connection) => self.upload(connection, contents),
+            None => Update::Finished(Ok((contents, Outcome::Read))),
+        }
+    }
+
+    fn upload(&self, connection: &Connection, contents: CardContents) -> Update {
+        match Server::connect(connection).and_then(|server| server.upload(&contents.registration)) {
+            Ok(()) => Update::Finished(Ok((contents, Outcome::Uploaded))),
+            Err(ServerError::AlreadyExists) => {
+                Update::Finished(Ok((contents, Outcome::AlreadyUploaded)))
+            }
+            Err(err) => Update::UploadFailed {
+                error: err.to_string(),
+                temporary: err.is_temporary(),
+                contents,
+            },
+        }
+    }
+}
+
+enum State {
+    Ready(Job),
+    Running(UnboundedReceiver<Update>),
+    Done,
+}
+
+/// Runs blocking work, like a request with the blocking client, on a thread of its own and
+/// waits for it without blocking the GUI.
+pub async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || {
+        let _ = sender.send(work());
+    });
+    receiver
+        .await
+        .unwrap_or_else(|_| Err("The background thread stopped unexpectedly.".to_string()))
+}
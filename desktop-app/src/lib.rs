@@ -0,0 +1,16 @@
+//! Reads vehicle registration certificates from smart cards and uploads them to a Vehikular
+//! server. The binary is the GUI, or a command line tool when given a command. The examples use
+//! the same reading and upload code.
+
+pub mod card_reading;
+pub mod cli;
+pub mod dump;
+pub mod gui;
+pub mod job;
+pub mod parsing;
+pub mod queue;
+pub mod reader;
+pub mod server;
+pub mod settings;
+pub mod viewer;
+pub mod watcher;
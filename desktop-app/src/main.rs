@@ -1,32 +1,97 @@
-use chrono::prelude::*;
-use gui::VehikularSettings;
+use std::process::ExitCode;
+
+use clap::Parser;
+use desktop_app::{
+    cli::{self, Command},
+    gui::VehikularSettings,
+    settings::Settings,
+};
 use iced::Application;
-use simplelog::ConfigBuilder;
+use log::LevelFilter;
+use simplelog::{ConfigBuilder, SharedLogger};
+
+/// Reads vehicle registration certificates and uploads them to a Vehikular server. Opens the
+/// window if no command is given.
+#[derive(Debug, Parser)]
+#[command(author, version, about, after_help = cli::EXIT_CODES)]
+struct Arguments {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Log the progress to the terminal. Commands only log warnings and errors otherwise.
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Also write the log of a command to a file in the log directory. The window writes one
+    /// whenever log files are turned on in the settings.
+    #[arg(long, global = true)]
+    log_file: bool,
+}
 
-mod card_reading;
-mod gui;
-mod parsing;
-mod reader;
+fn main() -> ExitCode {
+    let arguments = Arguments::parse();
+    let (settings, load_error) = match Settings::load() {
+        Ok(settings) => (settings, None),
+        Err(err) => (Settings::default(), Some(err.to_string())),
+    };
 
-fn main() -> Result<(), iced::Error> {
-    let time = Local::now().format("%Y-%m-%d %H-%M-%S");
-    let path = std::env::current_dir()
-        .expect("Could not get current dir")
-        .join(format!("Log {time}.txt"));
-    let file = std::fs::File::create(path).expect("Could not create log file.");
     let config = ConfigBuilder::new()
         .set_target_level(log::LevelFilter::Error)
         .add_filter_allow_str("desktop_app")
         .build();
-    simplelog::CombinedLogger::init(vec![
-        simplelog::TermLogger::new(
-            log::LevelFilter::Debug,
-            config.clone(),
-            simplelog::TerminalMode::Mixed,
-            simplelog::ColorChoice::Always,
+    // Commands print their results to stdout, so they log to stderr only. Their errors are only
+    // reported through the log, so those are always shown.
+    let (terminal_level, terminal_mode) = match &arguments.command {
+        None => (settings.log.level, simplelog::TerminalMode::Mixed),
+        Some(_) if arguments.verbose => (
+            settings.log.level.max(LevelFilter::Error),
+            simplelog::TerminalMode::Stderr,
         ),
-        simplelog::WriteLogger::new(log::LevelFilter::Debug, config, file),
-    ])
-    .expect("Could not create logging environtment.");
-    VehikularSettings::run(iced::Settings::default())
+        Some(_) => (
+            settings
+                .log
+                .level
+                .clamp(LevelFilter::Error, LevelFilter::Warn),
+            simplelog::TerminalMode::Stderr,
+        ),
+    };
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![simplelog::TermLogger::new(
+        terminal_level,
+        config.clone(),
+        terminal_mode,
+        simplelog::ColorChoice::Auto,
+    )];
+    // Commands are often run from scripts, which should not leave a log file behind on every
+    // call unless they ask for one.
+    let log_to_file = match &arguments.command {
+        None => settings.log.to_file,
+        Some(_) => arguments.log_file,
+    };
+    let mut log_file_error = None;
+    if log_to_file {
+        match settings.log.create_file() {
+            Ok(file) => loggers.push(simplelog::WriteLogger::new(
+                settings.log.level,
+                config,
+                file,
+            )),
+            Err(err) => log_file_error = Some(err),
+        }
+    }
+    simplelog::CombinedLogger::init(loggers).expect("Could not create logging environtment.");
+
+    if let Some(err) = &load_error {
+        log::error!("Could not load the settings, using the defaults: {err}");
+    }
+    if let Some(err) = &log_file_error {
+        log::error!("Could not create the log file: {err}");
+    }
+    match arguments.command {
+        Some(command) => cli::run(command, &settings),
+        None => match VehikularSettings::run(iced::Settings::with_flags((settings, load_error))) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                log::error!("{err}");
+                ExitCode::FAILURE
+            }
+        },
+    }
 }
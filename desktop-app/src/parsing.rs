@@ -1,4 +1,4 @@
-use std::{collections::HashMap};
+use std::{collections::{BTreeMap, HashMap}};
 
 use shared::data::{
     CertificateHolder, Engine, ExhaustEmisions, Mass, MaximumTowableMass, PersonalData,
@@ -9,6 +9,138 @@ use iso7816_tlv::ber::{
     Value::{Constructed, Primitive},
 };
 
+use crate::card_reading::File;
+
+/// Stands in for the fields that are missing on the card.
+pub const NOT_FOUND: &str = "Not found";
+
+/// The tags the [``Registration``] is made of and what they hold.
+pub const FIELDS: [(&str, &str); 31] = [
+    ("9F33", "Issuer state"),
+    ("9F35", "Issuer authority"),
+    ("9F38", "Document number"),
+    ("81", "Registration number"),
+    ("82", "Date of first registration"),
+    ("83", "Surname or business name"),
+    ("84", "Other name or initials"),
+    ("85", "Address"),
+    (
+        "86",
+        "Maximum permissible laden mass of the vehicle in service",
+    ),
+    ("87", "Make"),
+    ("88", "Vehicle type"),
+    ("89", "Commercial descriptions"),
+    ("8A", "Vehicle identification number"),
+    ("8B", "Maximum technically permissible laden mass"),
+    (
+        "97",
+        "Maximum permissible laden mass of the whole vehicle in service",
+    ),
+    ("8C", "Vehicle mass with body"),
+    ("8D", "Period of validity"),
+    ("8E", "Date of registration"),
+    ("8F", "Type approval number"),
+    ("90", "Capacity"),
+    ("91", "Max net power"),
+    ("92", "Fuel type"),
+    ("93", "Power weight ratio"),
+    ("94", "Number of seats"),
+    ("95", "Number of standing places"),
+    ("98", "Vehicle category"),
+    ("9B", "Braked"),
+    ("9C", "Unbraked"),
+    ("9F24", "Colour"),
+    ("25", "Maximum speed"),
+    ("9F32", "Environmental category"),
+];
+
+/// Everything that was read from a card, for when the [``Registration``] alone is not enough.
+#[derive(Debug, Clone)]
+pub struct CardContents {
+    pub registration: Registration,
+    /// The value of every primitive tag on the card, by its upper case hex encoded tag.
+    pub raw: BTreeMap<String, Vec<u8>>,
+    /// Everything that looked wrong whilst parsing, like missing tags.
+    pub diagnostics: Vec<String>,
+}
+
+impl CardContents {
+    pub fn new(registrations: &Vec<Tlv>) -> Self {
+        let hash_map = tlv_to_hash_map(registrations);
+        CardContents {
+            registration: combine_registrations(registrations),
+            raw: hash_map
+                .iter()
+                .map(|(tag, value)| (tag.to_uppercase(), (*value).clone()))
+                .collect(),
+            diagnostics: diagnose(&hash_map),
+        }
+    }
+
+    /// The name of the field stored under the tag, if it is used at all.
+    pub fn field_name(tag: &str) -> Option<&'static str> {
+        FIELDS
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(tag))
+            .map(|(_, name)| *name)
+    }
+
+    /// Everything as pretty printed JSON, with the raw values hex encoded.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let raw: BTreeMap<_, _> = self
+            .raw
+            .iter()
+            .map(|(tag, value)| (tag, hex::encode_upper(value)))
+            .collect();
+        serde_json::to_string_pretty(&serde_json::json!({
+            "registration": self.registration,
+            "raw": raw,
+            "diagnostics": self.diagnostics,
+        }))
+    }
+}
+
+fn diagnose(hash_map: &HashMap<String, &Vec<u8>>) -> Vec<String> {
+    let mut diagnostics = Vec::new();
+
+    for (tag, name) in FIELDS {
+        match hash_map.get(&tag.to_lowercase()) {
+            None => diagnostics.push(format!("{name} ({tag}) is missing.")),
+            Some(bytes) if std::str::from_utf8(bytes).is_err() => diagnostics.push(format!(
+                "{name} ({tag}) is not valid text, unreadable characters were replaced."
+            )),
+            Some(_) => {}
+        }
+    }
+    if let VehicleOwner::Unknown = hash_map.vehicle_owner() {
+        diagnostics
+            .push("Whether the holder owns the vehicle could not be determined (86).".into());
+    }
+
+    let mut unused: Vec<_> = hash_map
+        .keys()
+        .filter(|tag| CardContents::field_name(tag).is_none())
+        .map(|tag| tag.to_uppercase())
+        .collect();
+    unused.sort();
+    for tag in unused {
+        diagnostics.push(format!("Tag {tag} is not used by any field."));
+    }
+
+    diagnostics
+}
+
+/// Parses the registration files of a card, in the order they are stored on it. The security
+/// object holds no registration data and is left out.
+pub fn parse_files(files: &HashMap<File, Vec<u8>>) -> Vec<Tlv> {
+    File::ALL
+        .iter()
+        .filter(|file| **file != File::FSOd)
+        .filter_map(|file| files.get(file))
+        .flat_map(|bytes| Tlv::parse_all(bytes))
+        .collect()
+}
 
 pub fn combine_registrations(registrations: &Vec<Tlv>) -> Registration {
     let hash_map = tlv_to_hash_map(registrations);
@@ -101,7 +233,7 @@ impl HashMapToRegistrationHelper for HashMap<String, &Vec<u8>> {
         if let Some(bytes) = self.get(&path.to_lowercase()) {
             String::from_utf8_lossy(bytes).to_string()
         } else {
-            String::from("Not found")
+            String::from(NOT_FOUND)
         }
     }
 
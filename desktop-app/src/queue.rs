@@ -0,0 +1,289 @@
+use std::{any::TypeId, fs, io, path::PathBuf, thread, time::Duration as StdDuration};
+
+use chrono::{Duration, Local, NaiveDateTime};
+use iced::{
+    futures::{
+        channel::mpsc::{self, UnboundedReceiver},
+        future, StreamExt,
+    },
+    subscription, Subscription,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use shared::data::Registration;
+use thiserror::Error;
+
+use crate::settings::{write_private, APP_DIRECTORY};
+
+const FILE_NAME: &str = "upload-queue.json";
+/// How often to look for uploads that are due.
+const TICK: StdDuration = StdDuration::from_secs(5);
+/// How long to wait after the first failed attempt. The wait doubles with every further one.
+const RETRY_BASE_SECONDS: i64 = 30;
+const MAX_RETRY_MINUTES: i64 = 60;
+
+#[derive(Debug, Error)]
+pub enum QueueError {
+    #[error("There is no data directory on this platform.")]
+    NoDataDirectory,
+    #[error("Could not access the upload queue: {0}")]
+    Io(#[from] io::Error),
+    #[error("The upload queue is broken: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("The upload queue could not be read and was moved to {}: {0}", .1.display())]
+    Unreadable(serde_json::Error, PathBuf),
+}
+
+/// A card that was read but could not be uploaded yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedUpload {
+    pub id: u64,
+    pub registration: Registration,
+    /// The reader the card was read with. Every reader has a queue of its own, so a card that
+    /// keeps failing does not hold up the cards of the other readers. Missing in queues saved
+    /// by older versions.
+    #[serde(default)]
+    pub reader: Option<String>,
+    pub queued_at: NaiveDateTime,
+    pub attempts: u32,
+    pub last_error: String,
+    /// When it is tried again on its own. Never if the server refused the data, as trying again
+    /// would not change that.
+    pub retry_at: Option<NaiveDateTime>,
+    /// Being uploaded right now. Not saved, as an upload does not outlive the app.
+    #[serde(skip)]
+    pub uploading: bool,
+}
+
+/// The uploads that are still to be done. The queue is saved to the platform data directory
+/// after every change, so nothing is lost when the app is closed. The file holds personal data
+/// of the vehicle owners, so only the user may read it.
+#[derive(Debug, Default)]
+pub struct UploadQueue {
+    items: Vec<QueuedUpload>,
+    next_id: u64,
+}
+
+impl UploadQueue {
+    pub fn path() -> Result<PathBuf, QueueError> {
+        dirs::data_local_dir()
+            .map(|dir| dir.join(APP_DIRECTORY).join(FILE_NAME))
+            .ok_or(QueueError::NoDataDirectory)
+    }
+
+    /// Loads the saved queue, or an empty one if nothing has been queued yet. A queue that can
+    /// not be read is moved aside, so starting over with an empty one does not overwrite it.
+    pub fn load() -> Result<Self, QueueError> {
+        let path = Self::path()?;
+        match fs::read(&path) {
+            Ok(contents) => match serde_json::from_slice::<Vec<QueuedUpload>>(&contents) {
+                Ok(items) => {
+                    let next_id = items.iter().map(|item| item.id).max().unwrap_or_default();
+                    Ok(Self { items, next_id })
+                }
+                Err(err) => {
+                    let aside = path.with_file_name(format!(
+                        "upload-queue-unreadable-{}.json",
+                        Local::now().format("%Y%m%d-%H%M%S")
+                    ));
+                    fs::rename(&path, &aside)?;
+                    Err(QueueError::Unreadable(err, aside))
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self) -> Result<(), QueueError> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_private(&path, &serde_json::to_string_pretty(&self.items)?)?;
+        Ok(())
+    }
+
+    pub fn items(&self) -> &[QueuedUpload] {
+        &self.items
+    }
+
+    /// Queues a registration whose upload just failed.
+    pub fn push(
+        &mut self,
+        registration: Registration,
+        reader: Option<String>,
+        error: String,
+        temporary: bool,
+    ) -> Result<(), QueueError> {
+        let now = Local::now().naive_local();
+        self.next_id += 1;
+        self.items.push(QueuedUpload {
+            id: self.next_id,
+            registration,
+            reader,
+            queued_at: now,
+            attempts: 1,
+            last_error: error,
+            retry_at: temporary.then(|| now + backoff(1)),
+            uploading: false,
+        });
+        self.save()
+    }
+
+    /// Marks the first due upload as uploading and returns it. The uploads of a reader go one
+    /// at a time, so there is none for a reader while another one of it is running.
+    pub fn next_due(&mut self) -> Option<QueuedUpload> {
+        let busy: Vec<_> = self
+            .items
+            .iter()
+            .filter(|item| item.uploading)
+            .map(|item| item.reader.clone())
+            .collect();
+        let now = Local::now().naive_local();
+        let item = self
+            .items
+            .iter_mut()
+            .filter(|item| !busy.contains(&item.reader))
+            .filter(|item| item.retry_at.is_some_and(|retry_at| retry_at <= now))
+            .min_by_key(|item| item.retry_at)?;
+        item.uploading = true;
+        Some(item.clone())
+    }
+
+    /// Records how an upload went. Successful ones leave the queue.
+    pub fn finished(
+        &mut self,
+        id: u64,
+        result: Result<(), (String, bool)>,
+    ) -> Result<(), QueueError> {
+        match result {
+            Ok(()) => self.items.retain(|item| item.id != id),
+            Err((error, temporary)) => {
+                let Some(item) = self.items.iter_mut().find(|item| item.id == id) else {
+                    return Ok(());
+                };
+                item.uploading = false;
+                item.attempts += 1;
+                item.retry_at =
+                    temporary.then(|| Local::now().naive_local() + backoff(item.attempts));
+                item.last_error = error;
+            }
+        }
+        self.save()
+    }
+
+    /// Tries the upload again as soon as possible, even if the server refused it before.
+    pub fn retry_now(&mut self, id: u64) -> Result<(), QueueError> {
+        if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+            item.retry_at = Some(Local::now().naive_local());
+        }
+        self.save()
+    }
+
+    /// Drops an upload for good.
+    pub fn discard(&mut self, id: u64) -> Result<(), QueueError> {
+        self.items.retain(|item| item.id != id);
+        info!("Discarded queued upload {id}.");
+        self.save()
+    }
+}
+
+/// How long to wait after `attempts` failed attempts.
+fn backoff(attempts: u32) -> Duration {
+    let factor = 2_i64
+        .checked_pow(attempts.saturating_sub(1))
+        .unwrap_or(i64::MAX);
+    Duration::seconds(
+        RETRY_BASE_SECONDS
+            .saturating_mul(factor)
+            .min(MAX_RETRY_MINUTES * 60),
+    )
+}
+
+/// Fires every few seconds, so due uploads get started.
+pub fn ticks() -> Subscription<()> {
+    struct Ticks;
+
+    subscription::unfold(
+        TypeId::of::<Ticks>(),
+        None,
+        |receiver: Option<UnboundedReceiver<()>>| async move {
+            let mut receiver = receiver.unwrap_or_else(|| {
+                let (sender, receiver) = mpsc::unbounded();
+                // Sending only fails once the window is closed.
+                thread::spawn(move || {
+                    while sender.unbounded_send(()).is_ok() {
+                        thread::sleep(TICK);
+                    }
+                });
+                receiver
+            });
+            match receiver.next().await {
+                Some(()) => ((), Some(receiver)),
+                None => future::pending().await,
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::combine_registrations;
+
+    fn queued(id: u64, reader: &str, retry_in: Option<i64>) -> QueuedUpload {
+        let now = Local::now().naive_local();
+        QueuedUpload {
+            id,
+            registration: combine_registrations(&Vec::new()),
+            reader: Some(reader.to_string()),
+            queued_at: now,
+            attempts: 1,
+            last_error: String::new(),
+            retry_at: retry_in.map(|seconds| now + Duration::seconds(seconds)),
+            uploading: false,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        assert_eq!(backoff(1), Duration::seconds(30));
+        assert_eq!(backoff(2), Duration::seconds(60));
+        assert_eq!(backoff(4), Duration::seconds(240));
+        assert_eq!(backoff(10), Duration::minutes(MAX_RETRY_MINUTES));
+        assert_eq!(backoff(u32::MAX), Duration::minutes(MAX_RETRY_MINUTES));
+    }
+
+    #[test]
+    fn next_due_takes_the_longest_due_upload() {
+        let mut queue = UploadQueue {
+            items: vec![
+                queued(1, "A", Some(-10)),
+                queued(2, "B", Some(-60)),
+                queued(3, "C", Some(60)),
+                queued(4, "D", None),
+            ],
+            next_id: 4,
+        };
+        assert_eq!(queue.next_due().map(|item| item.id), Some(2));
+        assert_eq!(queue.next_due().map(|item| item.id), Some(1));
+        // Neither one that is not due yet nor one the server refused.
+        assert!(queue.next_due().is_none());
+    }
+
+    #[test]
+    fn next_due_uploads_one_card_per_reader_at_a_time() {
+        let mut queue = UploadQueue {
+            items: vec![
+                queued(1, "A", Some(-60)),
+                queued(2, "A", Some(-10)),
+                queued(3, "B", Some(-5)),
+            ],
+            next_id: 3,
+        };
+        assert_eq!(queue.next_due().map(|item| item.id), Some(1));
+        assert_eq!(queue.next_due().map(|item| item.id), Some(3));
+        assert!(queue.next_due().is_none());
+    }
+}
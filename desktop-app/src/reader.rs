@@ -1,32 +1,48 @@
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fmt::Display,
+    time::{Duration, Instant},
+};
 
 use color_eyre::Result;
 use log::{error, info};
-use pcsc::{Context, Protocols, ReaderState, Scope, ShareMode, State, PNP_NOTIFICATION};
+use pcsc::{
+    Attribute, Card, Context, Protocols, ReaderState, Scope, ShareMode, State, PNP_NOTIFICATION,
+};
+use shared::data::Registration;
 use thiserror::Error;
 
-use crate::card_reading::read_card;
-use shared::data::Registration;
+use crate::{
+    card_reading::{dump_card, read_card, CardReadingError, File},
+    dump::CardDump,
+    parsing::{CardContents, NOT_FOUND},
+};
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("The underlying card reader library returned an error: {0}")]
     Pcsc(#[from] pcsc::Error),
-    #[error("An error occured when interacting with the server: {0}")]
-    Http(#[from] reqwest::Error),
     #[error("Could not connect to card.")]
     ConectionFailure,
     #[error("Reader indicates no card found.")]
     CardNotFound,
+    #[error("Could not read the card: {0}")]
+    CardReading(#[from] CardReadingError),
     #[error("Attemted to use PNP notifcation as a reader")]
     PnpNotficationAsReader,
     #[error("The selected reader could not be found. Did you disconnect it?")]
     ReaderNotFound,
+    #[error("The card does not contain a registration number.")]
+    MissingRegistrationNumber,
+    #[error("Cancelled.")]
+    Cancelled,
 }
 
 pub struct Reader {
     ctx: Context,
     reader_states: Vec<ReaderState>,
+    /// The readers whose card has been reported and not taken out since.
     have_been_read: HashSet<String>,
 }
 
@@ -48,14 +64,50 @@ impl Reader {
     }
 
     pub fn update_readers(&mut self) -> Result<(), Error> {
+        self.refresh(Duration::from_millis(10))?;
+
+        let readers = self
+            .reader_states
+            .iter()
+            .map(|rs| format!("Name: {}; State: {:?};", rs.name().to_string_lossy(), rs.event_state()))
+            .collect::<Vec<String>>()
+            .join("\n         ");
+        info!("Readers:\n{readers}");
+
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for a card to be inserted and returns the readers holding a card
+    /// that has not been reported yet. Every card is only reported once, until it is removed.
+    /// Telling whether a card is one that was read a moment ago is up to [`RecentCards`], as
+    /// that is only known once it has been read.
+    pub fn wait_for_insertions(&mut self, timeout: Duration) -> Result<Vec<String>, Error> {
+        self.refresh(timeout)?;
+
+        let readers = self
+            .reader_states
+            .iter()
+            .filter(|rs| rs.name() != PNP_NOTIFICATION())
+            .map(|rs| {
+                (
+                    rs.name().to_string_lossy().to_string(),
+                    rs.event_state().contains(State::PRESENT),
+                )
+            });
+        Ok(newly_present(&mut self.have_been_read, readers))
+    }
+
+    /// Picks up added and removed readers, then waits up to `timeout` for the state of one of
+    /// them to change.
+    fn refresh(&mut self, timeout: Duration) -> Result<(), Error> {
         let mut readers_buf = [0; 2048];
         // Remove dead readers.
 
         for rs in &self.reader_states {
             if Self::is_dead(rs) {
-                println!("Removing {:?}", rs.name());
+                info!("Removing {:?}", rs.name());
                 self.have_been_read
-                    .remove(&rs.name().to_string_lossy().to_string());
+                    .remove(rs.name().to_string_lossy().as_ref());
             }
         }
         self.reader_states.retain(|rs| !Self::is_dead(rs));
@@ -64,7 +116,7 @@ impl Reader {
         let names = self.ctx.list_readers(&mut readers_buf)?;
         for name in names {
             if !self.reader_states.iter().any(|rs| rs.name() == name) {
-                println!("Adding {name:?}");
+                info!("Adding {name:?}");
                 self.reader_states
                     .push(ReaderState::new(name, State::UNAWARE));
             }
@@ -76,19 +128,11 @@ impl Reader {
         }
 
         // Wait until the state changes.
-        match self.ctx.get_status_change(Some(Duration::from_millis(10)), &mut self.reader_states) {
+        match self.ctx.get_status_change(Some(timeout), &mut self.reader_states) {
             Err(pcsc::Error::Timeout) | Ok(_) => {},
             Err(err) => Err(err)?
         };
 
-        let readers = self
-            .reader_states
-            .iter()
-            .map(|rs| format!("Name: {}; State: {:?};", rs.name().to_string_lossy(), rs.event_state()))
-            .collect::<Vec<String>>()
-            .join("\n         ");
-        info!("Readers:\n{readers}");
-
         Ok(())
     }
 
@@ -105,7 +149,23 @@ impl Reader {
             .collect()
     }
 
-    pub fn process_reader(&self, reader: &str, upload_address: &str) -> Result<(), Error> {
+    /// The connected readers and the state of their card, as of the last time they were looked
+    /// at.
+    pub fn card_states(&self) -> Vec<(String, CardState)> {
+        self.reader_states
+            .iter()
+            .filter(|rs| rs.name() != PNP_NOTIFICATION() && !Self::is_dead(rs))
+            .map(|rs| {
+                (
+                    rs.name().to_string_lossy().to_string(),
+                    CardState::from(rs.event_state()),
+                )
+            })
+            .collect()
+    }
+
+    /// Checks that the reader holds a card and returns a handle to read it with.
+    pub fn find_card(&self, reader: &str) -> Result<InsertedCard, Error> {
         let Some(reader) = self
             .reader_states
             .iter()
@@ -123,42 +183,279 @@ impl Reader {
         if !reader.event_state().contains(State::PRESENT) {
             Err(Error::CardNotFound)?;
         }
-        // Connect to the card.
-        let card = match self
-            .ctx
-            .connect(reader.name(), ShareMode::Shared, Protocols::ANY)
-        {
-            Ok(card) => card,
-            Err(err) => {
-                error!("Failed to connect to card: {err}");
-                Err(Error::ConectionFailure)?
+
+        Ok(InsertedCard {
+            ctx: self.ctx.clone(),
+            reader: reader.name().to_owned(),
+        })
+    }
+}
+
+/// Returns the readers that hold a card which is not in `have_been_read` yet and adds them to it.
+/// Readers that are empty are taken out again, so their next card is reported.
+fn newly_present(
+    have_been_read: &mut HashSet<String>,
+    readers: impl IntoIterator<Item = (String, bool)>,
+) -> Vec<String> {
+    let mut inserted = Vec::new();
+    for (name, present) in readers {
+        if !present {
+            have_been_read.remove(&name);
+        } else if have_been_read.insert(name.clone()) {
+            inserted.push(name);
+        }
+    }
+    inserted
+}
+
+/// The cards that were read automatically a moment ago. A card that is read again within the
+/// dedupe window, because it slipped out and was put back or was moved to another reader, is
+/// taken to be the same read and not handled twice.
+///
+/// Cards are told apart by their document and registration number, as every card of a kind
+/// answers with the same ATR.
+#[derive(Debug, Default)]
+pub struct RecentCards {
+    window: Duration,
+    read_at: HashMap<(String, String), Instant>,
+}
+
+impl RecentCards {
+    pub fn new(window: Duration) -> Self {
+        RecentCards {
+            window,
+            read_at: HashMap::new(),
+        }
+    }
+
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Notes that the card was read at `now`. If it was read within the window before that,
+    /// returns how long ago that was, and the card should not be handled again.
+    pub fn note(&mut self, registration: &Registration, now: Instant) -> Option<Duration> {
+        self.note_card(
+            &registration.document_number,
+            &registration.registration_number,
+            now,
+        )
+    }
+
+    fn note_card(
+        &mut self,
+        document_number: &str,
+        registration_number: &str,
+        now: Instant,
+    ) -> Option<Duration> {
+        let window = self.window;
+        self.read_at
+            .retain(|_, read_at| now.saturating_duration_since(*read_at) < window);
+        let card = (document_number.to_string(), registration_number.to_string());
+        match self.read_at.get(&card) {
+            Some(read_at) => Some(now.saturating_duration_since(*read_at)),
+            None => {
+                if !window.is_zero() {
+                    self.read_at.insert(card, now);
+                }
+                None
             }
-        };
+        }
+    }
+}
+
+/// Whether a reader holds a card and what is happening with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardState {
+    Empty,
+    Present,
+    /// Another application, or another job, is using the card.
+    InUse,
+    /// The card does not answer, e.g. because it is inserted the wrong way around.
+    Mute,
+}
+
+impl From<State> for CardState {
+    fn from(state: State) -> Self {
+        if !state.contains(State::PRESENT) {
+            CardState::Empty
+        } else if state.contains(State::MUTE) {
+            CardState::Mute
+        } else if state.intersects(State::INUSE | State::EXCLUSIVE) {
+            CardState::InUse
+        } else {
+            CardState::Present
+        }
+    }
+}
+
+impl Display for CardState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CardState::Empty => write!(f, "Empty"),
+            CardState::Present => write!(f, "Card present"),
+            CardState::InUse => write!(f, "In use"),
+            CardState::Mute => write!(f, "Card does not answer"),
+        }
+    }
+}
+
+/// The steps of reading and uploading a card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    Connecting,
+    Reading(File),
+    Verifying,
+    Uploading,
+}
+
+impl Display for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Progress::Connecting => write!(f, "Connecting to the card"),
+            Progress::Reading(File::RegistrationA) => write!(f, "Reading registration A"),
+            Progress::Reading(File::RegistrationB) => write!(f, "Reading registration B"),
+            Progress::Reading(File::RegistrationC) => write!(f, "Reading registration C"),
+            Progress::Reading(File::FSOd) => write!(f, "Reading the security object"),
+            Progress::Verifying => write!(f, "Verifying the data"),
+            Progress::Uploading => write!(f, "Uploading"),
+        }
+    }
+}
+
+/// A card in a reader. Unlike the [`Reader`] it can be moved to another thread to read the card there.
+#[derive(Clone)]
+pub struct InsertedCard {
+    ctx: Context,
+    reader: CString,
+}
+
+impl InsertedCard {
+    /// Reads the card. `progress` is called at the start of every step, returning `false` from it
+    /// cancels the reading.
+    pub fn read(&self, mut progress: impl FnMut(Progress) -> bool) -> Result<CardContents, Error> {
+        let card = self.connect(&mut progress)?;
 
         info!("Found a card. Attempting read.");
-        match read_card(&card) {
-            Ok(registration) => {
-                info!("Read successful. Uploading.");
-                upload(&registration, upload_address)?;
-            }
+        let contents =
+            read_card(&card, |file| progress(Progress::Reading(file))).map_err(reading_failed)?;
+
+        if !progress(Progress::Verifying) {
+            Err(Error::Cancelled)?;
+        }
+        require_registration_number(&contents)?;
+
+        Ok(contents)
+    }
+
+    /// Reads every file of the card without parsing it. `progress` works like it does for
+    /// [`InsertedCard::read`].
+    pub fn dump(&self, mut progress: impl FnMut(Progress) -> bool) -> Result<CardDump, Error> {
+        let card = self.connect(&mut progress)?;
+        let atr = card.get_attribute_owned(Attribute::AtrString)?;
+
+        info!("Found a card. Attempting dump.");
+        let files =
+            dump_card(&card, |file| progress(Progress::Reading(file))).map_err(reading_failed)?;
+        Ok(CardDump::new(
+            self.reader.to_string_lossy().into_owned(),
+            &atr,
+            files,
+        ))
+    }
+
+    fn connect(&self, progress: &mut impl FnMut(Progress) -> bool) -> Result<Card, Error> {
+        if !progress(Progress::Connecting) {
+            Err(Error::Cancelled)?;
+        }
+        match self
+            .ctx
+            .connect(&self.reader, ShareMode::Shared, Protocols::ANY)
+        {
+            Ok(card) => Ok(card),
             Err(err) => {
-                error!("Failed to read card. {err}");
+                error!("Failed to connect to card: {err}");
+                Err(Error::ConectionFailure)
             }
         }
-
-        Ok(())
     }
 }
 
-fn upload(registration: &Registration, upload_address: &str) -> Result<(), reqwest::Error> {
-    let client = reqwest::blocking::ClientBuilder::new().build()?;
-    client
-        .post(format!("http://{upload_address}/registration"))
-        .json(&registration)
-        .send()?;
-    println!(
-        "Uploaded sucefully. Should be available under: http://{upload_address}/registration/{}",
-        registration.registration_number
-    );
+/// Checks that the contents have a registration number, as the server files every vehicle
+/// under it.
+pub fn require_registration_number(contents: &CardContents) -> Result<(), Error> {
+    let registration_number = &contents.registration.registration_number;
+    if registration_number == NOT_FOUND || registration_number.trim().is_empty() {
+        Err(Error::MissingRegistrationNumber)?;
+    }
     Ok(())
 }
+
+fn reading_failed(err: CardReadingError) -> Error {
+    match err {
+        CardReadingError::Cancelled => Error::Cancelled,
+        err => {
+            error!("Failed to read card. {err}");
+            err.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readers(states: &[(&str, bool)]) -> Vec<(String, bool)> {
+        states
+            .iter()
+            .map(|(name, present)| (name.to_string(), *present))
+            .collect()
+    }
+
+    #[test]
+    fn reports_a_card_once_until_it_is_removed() {
+        let mut have_been_read = HashSet::new();
+        let inserted = newly_present(&mut have_been_read, readers(&[("A", true), ("B", false)]));
+        assert_eq!(inserted, ["A"]);
+        let inserted = newly_present(&mut have_been_read, readers(&[("A", true), ("B", false)]));
+        assert!(inserted.is_empty());
+
+        newly_present(&mut have_been_read, readers(&[("A", false), ("B", false)]));
+        let inserted = newly_present(&mut have_been_read, readers(&[("A", true), ("B", true)]));
+        assert_eq!(inserted, ["A", "B"]);
+    }
+
+    #[test]
+    fn skips_the_same_card_within_the_window() {
+        let mut recent = RecentCards::new(Duration::from_secs(5));
+        let start = Instant::now();
+        assert_eq!(recent.note_card("D1", "W-1", start), None);
+        assert_eq!(
+            recent.note_card("D1", "W-1", start + Duration::from_secs(2)),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            recent.note_card("D1", "W-1", start + Duration::from_secs(6)),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_a_different_card_within_the_window() {
+        let mut recent = RecentCards::new(Duration::from_secs(5));
+        let start = Instant::now();
+        assert_eq!(recent.note_card("D1", "W-1", start), None);
+        assert_eq!(
+            recent.note_card("D2", "W-2", start + Duration::from_secs(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn skips_nothing_without_a_window() {
+        let mut recent = RecentCards::default();
+        let now = Instant::now();
+        assert_eq!(recent.note_card("D1", "W-1", now), None);
+        assert_eq!(recent.note_card("D1", "W-1", now), None);
+    }
+}
@@ -0,0 +1,403 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use log::info;
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    Certificate, StatusCode, Url,
+};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    ClientConfig, ServerName,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::data::Registration;
+use thiserror::Error;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A setting that can not be used.
+#[derive(Debug, Error)]
+#[error("The {0} {1}.")]
+pub struct InvalidSetting(pub &'static str, pub &'static str);
+
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error(transparent)]
+    Invalid(#[from] InvalidSetting),
+    #[error("Could not read the certificate {0}: {1}")]
+    Certificate(PathBuf, io::Error),
+    #[error("Could not set up the connection: {0}")]
+    Setup(reqwest::Error),
+    #[error("The server did not answer within {0} seconds.")]
+    Timeout(u64),
+    #[error("Could not reach the server: {0}")]
+    Unreachable(reqwest::Error),
+    #[error("The server rejected the username or password.")]
+    Unauthorized,
+    #[error("This vehicle is already on the server.")]
+    AlreadyExists,
+    #[error("The server rejected the card data: {0}")]
+    Rejected(String),
+    #[error("The server could not understand the card data. Is it running the same version?")]
+    Incompatible,
+    #[error("The server ran into an error ({0}): {1}")]
+    Failed(StatusCode, String),
+    #[error("The server answered with {0}: {1}")]
+    UnexpectedStatus(StatusCode, String),
+}
+
+impl ServerError {
+    /// Whether trying again later may work. Card data the server refused stays refused.
+    pub fn is_temporary(&self) -> bool {
+        !matches!(
+            self,
+            ServerError::AlreadyExists | ServerError::Rejected(_) | ServerError::Incompatible
+        )
+    }
+}
+
+/// Sent as HTTP basic authentication, for servers behind a proxy that asks for a login.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn is_empty(&self) -> bool {
+        self.username.is_empty() && self.password.is_empty()
+    }
+}
+
+/// How to reach the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    /// Host and port, e.g. `localhost:8000`, optionally starting with `http://` or `https://`.
+    /// Plain HTTP is used if there is no scheme.
+    pub address: String,
+    pub credentials: Credentials,
+    /// A PEM file with a certificate authority that is trusted on top of the system ones, for
+    /// servers with a certificate from their own authority.
+    pub ca_certificate: Option<PathBuf>,
+    /// The SHA-256 fingerprint of the certificate of the server, in hex. Only that exact
+    /// certificate is accepted then, which also works for self-signed ones.
+    pub pinned_certificate: Option<String>,
+    /// How long to wait for the server, both to connect and to answer.
+    pub timeout: Duration,
+}
+
+impl Connection {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            credentials: Credentials::default(),
+            ca_certificate: None,
+            pinned_certificate: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// The address with a scheme and without a trailing slash.
+    pub fn base_url(&self) -> String {
+        let address = self.address.trim().trim_end_matches('/');
+        if address.starts_with("http://") || address.starts_with("https://") {
+            address.to_string()
+        } else {
+            format!("http://{address}")
+        }
+    }
+
+    /// The page of the vehicle on the server.
+    pub fn registration_url(&self, registration_number: &str) -> String {
+        format!("{}/registration/{registration_number}", self.base_url())
+    }
+
+    pub fn validate(&self) -> Result<(), InvalidSetting> {
+        let address = self.address.trim();
+        if address.is_empty() {
+            return Err(InvalidSetting("address", "must not be empty"));
+        }
+        let Ok(url) = Url::parse(&self.base_url()) else {
+            return Err(InvalidSetting("address", "is not a valid host"));
+        };
+        if url.host_str().is_none() || !matches!(url.scheme(), "http" | "https") {
+            return Err(InvalidSetting("address", "is not a valid host"));
+        }
+        if url.path() != "/" || url.query().is_some() {
+            return Err(InvalidSetting(
+                "address",
+                "must only be a host and port, e.g. localhost:8000",
+            ));
+        }
+        if self.credentials.username.contains(':') {
+            return Err(InvalidSetting("username", "must not contain a colon"));
+        }
+        if self.credentials.username.is_empty() && !self.credentials.password.is_empty() {
+            return Err(InvalidSetting("username", "is missing for the password"));
+        }
+        if let Some(path) = &self.ca_certificate {
+            if !path.is_file() {
+                return Err(InvalidSetting("certificate authority", "does not exist"));
+            }
+        }
+        if let Some(fingerprint) = &self.pinned_certificate {
+            if parse_fingerprint(fingerprint).is_none() {
+                return Err(InvalidSetting(
+                    "pinned certificate",
+                    "must be a SHA-256 fingerprint of 64 hex digits",
+                ));
+            }
+            if url.scheme() != "https" {
+                return Err(InvalidSetting(
+                    "pinned certificate",
+                    "needs an address starting with https://",
+                ));
+            }
+        }
+        if self.timeout.is_zero() {
+            return Err(InvalidSetting("timeout", "must be at least a second"));
+        }
+        Ok(())
+    }
+}
+
+/// Reads a fingerprint like `AB:CD:...` or `abcd...`.
+fn parse_fingerprint(fingerprint: &str) -> Option<[u8; 32]> {
+    let digits: String = fingerprint
+        .chars()
+        .filter(|c| !matches!(c, ':' | ' '))
+        .collect();
+    hex::decode(digits).ok()?.try_into().ok()
+}
+
+/// Accepts exactly one certificate, whoever signed it.
+struct PinnedCertificate([u8; 32]);
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if Sha256::digest(&end_entity.0).as_slice() == self.0 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "The certificate of the server does not match the pinned one.".to_string(),
+            ))
+        }
+    }
+}
+
+/// The Vehikular server cards are uploaded to.
+#[derive(Debug, Clone)]
+pub struct Server {
+    connection: Connection,
+    client: Client,
+}
+
+impl Server {
+    /// Sets up a client for the connection. This does not contact the server yet.
+    ///
+    /// The client has a runtime of its own, so this must not be called from async code.
+    pub fn connect(connection: &Connection) -> Result<Self, ServerError> {
+        connection.validate()?;
+        let mut builder = Client::builder()
+            .timeout(connection.timeout)
+            .connect_timeout(connection.timeout);
+        if let Some(fingerprint) = connection
+            .pinned_certificate
+            .as_deref()
+            .and_then(parse_fingerprint)
+        {
+            let tls = ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(PinnedCertificate(fingerprint)))
+                .with_no_client_auth();
+            builder = builder.use_preconfigured_tls(tls);
+        } else if let Some(path) = &connection.ca_certificate {
+            let pem = fs::read(path).map_err(|err| ServerError::Certificate(path.clone(), err))?;
+            builder = builder
+                .add_root_certificate(Certificate::from_pem(&pem).map_err(ServerError::Setup)?);
+        }
+
+        Ok(Self {
+            connection: connection.clone(),
+            client: builder.build().map_err(ServerError::Setup)?,
+        })
+    }
+
+    fn authenticate(&self, request: RequestBuilder) -> RequestBuilder {
+        let credentials = &self.connection.credentials;
+        if credentials.is_empty() {
+            request
+        } else {
+            request.basic_auth(&credentials.username, Some(&credentials.password))
+        }
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<Response, ServerError> {
+        self.authenticate(request).send().map_err(|err| {
+            if err.is_timeout() {
+                ServerError::Timeout(self.connection.timeout.as_secs())
+            } else {
+                ServerError::Unreachable(err)
+            }
+        })
+    }
+
+    pub fn upload(&self, registration: &Registration) -> Result<(), ServerError> {
+        let request = self
+            .client
+            .post(format!("{}/registration", self.connection.base_url()))
+            .json(&registration);
+        error_for_status(self.send(request)?)?;
+        info!(
+            "Uploaded sucefully. Should be available under: {}",
+            self.connection
+                .registration_url(&registration.registration_number)
+        );
+        Ok(())
+    }
+
+    /// Checks whether the server knows the vehicle.
+    pub fn is_on_server(&self, registration_number: &str) -> Result<bool, ServerError> {
+        let request = self
+            .client
+            .get(self.connection.registration_url(registration_number));
+        let response = self.send(request)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        error_for_status(response)?;
+        Ok(true)
+    }
+
+    /// Checks that the server can be reached and accepts the credentials, by fetching the
+    /// login page, which needs no Vehikular account.
+    pub fn test_connection(&self) -> Result<(), ServerError> {
+        let request = self
+            .client
+            .get(format!("{}/login", self.connection.base_url()));
+        error_for_status(self.send(request)?)?;
+        Ok(())
+    }
+}
+
+/// Turns responses other than 2xx into errors. The server explains its errors in a plain text
+/// body, like the `RegistrationError`s of uploads.
+fn error_for_status(response: Response) -> Result<Response, ServerError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().unwrap_or_default().trim().to_string();
+    Err(status_error(status, body))
+}
+
+/// The error for a response with a status other than 2xx and the given body.
+fn status_error(status: StatusCode, body: String) -> ServerError {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ServerError::Unauthorized,
+        StatusCode::CONFLICT => ServerError::AlreadyExists,
+        StatusCode::BAD_REQUEST => ServerError::Rejected(body),
+        // Rocket answers like this when the JSON does not fit the registration it expects.
+        StatusCode::UNPROCESSABLE_ENTITY => ServerError::Incompatible,
+        status if status.is_server_error() => ServerError::Failed(status, body),
+        status => ServerError::UnexpectedStatus(status, body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: [u8; 32] = [
+        0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67,
+        0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45,
+        0x67, 0x89,
+    ];
+
+    #[test]
+    fn fingerprints_are_read_with_or_without_separators() {
+        let plain = "abcdef0123456789".repeat(4);
+        let separated = plain
+            .to_uppercase()
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| String::from_utf8_lossy(pair).into_owned())
+            .collect::<Vec<_>>()
+            .join(":");
+
+        assert_eq!(parse_fingerprint(&plain), Some(DIGEST));
+        assert_eq!(parse_fingerprint(&separated), Some(DIGEST));
+        assert_eq!(
+            parse_fingerprint(&separated.replace(':', " ")),
+            Some(DIGEST)
+        );
+    }
+
+    #[test]
+    fn fingerprints_of_the_wrong_length_or_with_other_characters_are_refused() {
+        assert_eq!(parse_fingerprint(&"ab".repeat(31)), None);
+        assert_eq!(parse_fingerprint(&"ab".repeat(33)), None);
+        assert_eq!(parse_fingerprint(&"zz".repeat(32)), None);
+        assert_eq!(parse_fingerprint(""), None);
+    }
+
+    #[test]
+    fn statuses_are_turned_into_errors() {
+        let error = |status| status_error(status, "Registration number is missing".to_string());
+
+        assert!(matches!(
+            error(StatusCode::UNAUTHORIZED),
+            ServerError::Unauthorized
+        ));
+        assert!(matches!(
+            error(StatusCode::FORBIDDEN),
+            ServerError::Unauthorized
+        ));
+        assert!(matches!(
+            error(StatusCode::CONFLICT),
+            ServerError::AlreadyExists
+        ));
+        assert!(matches!(
+            error(StatusCode::BAD_REQUEST),
+            ServerError::Rejected(body) if body == "Registration number is missing"
+        ));
+        assert!(matches!(
+            error(StatusCode::UNPROCESSABLE_ENTITY),
+            ServerError::Incompatible
+        ));
+        assert!(matches!(
+            error(StatusCode::BAD_GATEWAY),
+            ServerError::Failed(StatusCode::BAD_GATEWAY, _)
+        ));
+        assert!(matches!(
+            error(StatusCode::NOT_FOUND),
+            ServerError::UnexpectedStatus(StatusCode::NOT_FOUND, _)
+        ));
+    }
+
+    #[test]
+    fn only_refused_card_data_is_final() {
+        let error = |status| status_error(status, String::new());
+
+        assert!(!error(StatusCode::CONFLICT).is_temporary());
+        assert!(!error(StatusCode::BAD_REQUEST).is_temporary());
+        assert!(!error(StatusCode::UNPROCESSABLE_ENTITY).is_temporary());
+        assert!(error(StatusCode::SERVICE_UNAVAILABLE).is_temporary());
+        assert!(error(StatusCode::UNAUTHORIZED).is_temporary());
+    }
+}
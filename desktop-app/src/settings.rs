@@ -0,0 +1,214 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::Local;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::server::{self, Connection, Credentials, InvalidSetting};
+
+/// Directory below the platform config and data directories that holds the files of the app.
+pub(crate) const APP_DIRECTORY: &str = "vehikular";
+const FILE_NAME: &str = "desktop.toml";
+const DEFAULT_DEDUPE_SECONDS: u64 = 5;
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("There is no config directory on this platform.")]
+    NoConfigDirectory,
+    #[error("Could not access the settings file: {0}")]
+    Io(#[from] io::Error),
+    #[error("The settings file could not be read and was moved to {}: {0}", .1.display())]
+    Unreadable(toml::de::Error, PathBuf),
+    #[error("Could not write the settings: {0}")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+/// Everything the desktop app remembers between runs. Missing values fall back to their
+/// defaults, so older settings files keep working.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Host and port of the server, e.g. `localhost:8000`, optionally starting with `https://`.
+    pub address: String,
+    /// The reader to use when it is connected.
+    pub preferred_reader: Option<String>,
+    pub auto_upload: bool,
+    pub auto_open: bool,
+    /// Read cards inserted into any reader, not only the preferred one.
+    pub all_readers: bool,
+    /// How long after a card was read on its own it is left alone when it is read again, in the
+    /// same reader or another one.
+    pub dedupe_seconds: u64,
+    pub credentials: Credentials,
+    /// See [`Connection::ca_certificate`].
+    pub ca_certificate: Option<PathBuf>,
+    /// See [`Connection::pinned_certificate`].
+    pub pinned_certificate: Option<String>,
+    pub timeout_seconds: u64,
+    pub log: LogSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            address: String::new(),
+            preferred_reader: None,
+            auto_upload: false,
+            auto_open: false,
+            all_readers: false,
+            dedupe_seconds: DEFAULT_DEDUPE_SECONDS,
+            credentials: Credentials::default(),
+            ca_certificate: None,
+            pinned_certificate: None,
+            timeout_seconds: server::DEFAULT_TIMEOUT.as_secs(),
+            log: LogSettings::default(),
+        }
+    }
+}
+
+/// Where and how much the app logs. Changes take effect on the next start.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogSettings {
+    pub level: LevelFilter,
+    pub to_file: bool,
+    /// Where log files are written. The working directory if there is none.
+    pub directory: Option<PathBuf>,
+}
+
+impl LogSettings {
+    /// Creates a new log file in the log directory. Only the user may read it, as the log holds
+    /// the data read from cards.
+    pub fn create_file(&self) -> io::Result<fs::File> {
+        let directory = match &self.directory {
+            Some(directory) => directory.clone(),
+            None => std::env::current_dir()?,
+        };
+        let time = Local::now().format("%Y-%m-%d %H-%M-%S");
+        create_private(&directory.join(format!("Log {time}.txt")))
+    }
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Debug,
+            to_file: true,
+            directory: None,
+        }
+    }
+}
+
+impl Settings {
+    /// The settings file in the platform config directory.
+    pub fn path() -> Result<PathBuf, SettingsError> {
+        dirs::config_dir()
+            .map(|dir| dir.join(APP_DIRECTORY).join(FILE_NAME))
+            .ok_or(SettingsError::NoConfigDirectory)
+    }
+
+    /// Loads the saved settings, or the defaults if none have been saved yet. A settings file
+    /// that can not be read is moved aside, so saving the defaults does not overwrite it.
+    pub fn load() -> Result<Self, SettingsError> {
+        let path = Self::path()?;
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).or_else(|err| {
+                let aside = path.with_file_name(format!(
+                    "desktop-unreadable-{}.toml",
+                    Local::now().format("%Y%m%d-%H%M%S")
+                ));
+                fs::rename(&path, &aside)?;
+                Err(SettingsError::Unreadable(err, aside))
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Saves the settings. They are saved even if they are invalid, like an address that is
+    /// still being typed, and only checked when connecting to the server.
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_private(&path, &toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// How to reach the server.
+    pub fn connection(&self) -> Connection {
+        Connection {
+            address: self.address.clone(),
+            credentials: self.credentials.clone(),
+            ca_certificate: self.ca_certificate.clone(),
+            pinned_certificate: self.pinned_certificate.clone(),
+            timeout: Duration::from_secs(self.timeout_seconds),
+        }
+    }
+
+    pub fn dedupe_window(&self) -> Duration {
+        Duration::from_secs(self.dedupe_seconds)
+    }
+
+    pub fn validate(&self) -> Result<(), InvalidSetting> {
+        self.connection().validate()?;
+        if let Some(directory) = &self.log.directory {
+            if !directory.is_dir() {
+                return Err(InvalidSetting("log directory", "does not exist"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes a file only the user may read, as it holds a password or personal data. The contents
+/// go to a temporary file first, which then replaces the file, so a crash while writing leaves
+/// the old contents in place instead of a half written file.
+pub(crate) fn write_private(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    let mut file = create_private(&temp)?;
+    file.write_all(contents.as_ref())?;
+    file.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+#[cfg(unix)]
+fn create_private(path: &Path) -> io::Result<fs::File> {
+    use std::{
+        fs::Permissions,
+        os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    };
+
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode only applies to new files.
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+/// Elsewhere the file keeps the permissions it inherits from its directory. On Windows that is
+/// the profile of the user for the default locations, which other users can not read, but a
+/// log directory chosen in the settings may well be shared.
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> io::Result<fs::File> {
+    log::warn!(
+        "{} is not restricted to the current user on this platform, it is only as private as \
+         its directory.",
+        path.display()
+    );
+    fs::File::create(path)
+}
@@ -0,0 +1,171 @@
+use iced::{
+    widget::{button, column, row, scrollable, text, Column},
+    Element, Length,
+};
+
+use crate::{gui::Message, parsing::CardContents};
+
+/// Shows everything that was read from a card, without sending any of it to the server.
+pub fn view(contents: &CardContents) -> Element<'_, Message> {
+    let registration = &contents.registration;
+    let owner = registration.personal_data.vehicles_owner.to_string();
+
+    let actions = row![
+        button("Copy as JSON").on_press(Message::CopyJson),
+        button("Save to file").on_press(Message::SaveJson),
+        button("View on the web").on_press(Message::ViewCardWeb),
+        button("Back").on_press(Message::CloseViewer),
+    ]
+    .spacing(5);
+
+    let general = section(
+        "Registration",
+        &[
+            ("Issuer state", &registration.issuer_state),
+            ("Issuer authority", &registration.issuer_authority),
+            ("Document number", &registration.document_number),
+            ("Registration number", &registration.registration_number),
+            (
+                "Date of first registration",
+                &registration.date_of_first_registration,
+            ),
+            (
+                "Vehicle identification number",
+                &registration.vehicle_identification_number,
+            ),
+            (
+                "Vehicle mass with body",
+                &registration.vehicle_mass_with_body,
+            ),
+            ("Period of validity", &registration.period_of_validity),
+            ("Date of registration", &registration.date_of_registration),
+            ("Type approval number", &registration.type_approval_number),
+            ("Power weight ratio", &registration.power_weight_ratio),
+            ("Vehicle category", &registration.vechicle_category),
+            ("Colour", &registration.colour),
+            ("Maximum speed", &registration.maximum_speed),
+        ],
+    );
+    let holder = &registration.personal_data.certificate_holder;
+    let personal_data = section(
+        "Personal data",
+        &[
+            ("Surname or business name", &holder.surname_or_business_name),
+            ("Other name or initials", &holder.other_name_or_initials),
+            ("Address", &holder.address),
+            ("Vehicle owner", &owner),
+        ],
+    );
+    let vehicle = section(
+        "Vehicle",
+        &[
+            ("Make", &registration.vehicle.make),
+            ("Vehicle type", &registration.vehicle.vehicle_type),
+            (
+                "Commercial descriptions",
+                &registration.vehicle.commercial_descriptons,
+            ),
+        ],
+    );
+    let mass = section(
+        "Mass",
+        &[
+            (
+                "Maximum technically permissible laden mass",
+                &registration.mass.maximum_technically_permissible_laden_mass,
+            ),
+            (
+                "Maximum permissible laden mass of the vehicle in service",
+                &registration
+                    .mass
+                    .maximum_permissible_laden_mass_of_the_vehicle_in_service,
+            ),
+            (
+                "Maximum permissible laden mass of the whole vehicle in service",
+                &registration
+                    .mass
+                    .maximum_permissible_laden_mass_of_the_whole_vehicle_in_service,
+            ),
+        ],
+    );
+    let engine = section(
+        "Engine",
+        &[
+            ("Capacity", &registration.engine.capacity),
+            ("Max net power", &registration.engine.max_net_power),
+            ("Fuel type", &registration.engine.fuel_type),
+        ],
+    );
+    let seating_capacity = section(
+        "Seating capacity",
+        &[
+            (
+                "Number of seats",
+                &registration.seating_capacity.number_of_seats,
+            ),
+            (
+                "Number of standing places",
+                &registration.seating_capacity.nunmber_of_standing_places,
+            ),
+        ],
+    );
+    let towable_mass = section(
+        "Maximum towable mass",
+        &[
+            ("Braked", &registration.maximum_towable_mass.braked),
+            ("Unbraked", &registration.maximum_towable_mass.unbraked),
+        ],
+    );
+    let exhaust_emissions = section(
+        "Exhaust emissions",
+        &[(
+            "Environmental category",
+            &registration.exhaust_emissions.environmental_category,
+        )],
+    );
+
+    let mut diagnostics = column![text("Diagnostics").size(24)].spacing(2);
+    if contents.diagnostics.is_empty() {
+        diagnostics = diagnostics.push(text("Nothing looked wrong."));
+    }
+    for diagnostic in &contents.diagnostics {
+        diagnostics = diagnostics.push(text(diagnostic));
+    }
+
+    let mut raw = column![text("Raw tags").size(24)].spacing(2);
+    for (tag, value) in &contents.raw {
+        let name = CardContents::field_name(tag).unwrap_or("Unused");
+        raw = raw.push(text(format!(
+            "{tag} ({name}): {} \"{}\"",
+            hex::encode_upper(value),
+            String::from_utf8_lossy(value)
+        )));
+    }
+
+    let content = column![
+        text(&registration.registration_number).size(30),
+        actions,
+        general,
+        personal_data,
+        vehicle,
+        mass,
+        engine,
+        seating_capacity,
+        towable_mass,
+        exhaust_emissions,
+        diagnostics,
+        raw,
+    ]
+    .padding(10)
+    .spacing(10);
+
+    scrollable(content).height(Length::Fill).into()
+}
+
+fn section<'a>(title: &str, fields: &[(&str, &String)]) -> Column<'a, Message> {
+    let mut section = column![text(title).size(24)].spacing(2);
+    for (label, value) in fields {
+        section = section.push(text(format!("{label}: {value}")));
+    }
+    section
+}
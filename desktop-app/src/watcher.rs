@@ -0,0 +1,92 @@
+use std::{any::TypeId, thread, time::Duration};
+
+use iced::{
+    futures::{
+        channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+        future, StreamExt,
+    },
+    subscription, Subscription,
+};
+use log::{error, info};
+
+use crate::reader::{CardState, Reader};
+
+/// How long to wait for a card before looking for newly connected readers again.
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Something the watcher noticed.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A reader was connected or disconnected, or the card in one changed. Holds every connected
+    /// reader and the state of its card.
+    ReadersChanged(Vec<(String, CardState)>),
+    /// A card was put into the reader with this name.
+    CardInserted(String),
+    /// The watcher ran into an error and stopped.
+    Stopped(String),
+}
+
+/// Watches all readers on a background thread and reports every card once when it is inserted.
+/// Readers that are connected or disconnected are picked up right away where PC/SC supports
+/// notifications about them, and within a second elsewhere.
+pub fn watch() -> Subscription<Event> {
+    struct Watcher;
+
+    subscription::unfold(
+        TypeId::of::<Watcher>(),
+        None,
+        move |receiver: Option<UnboundedReceiver<Event>>| async move {
+            let mut receiver = receiver.unwrap_or_else(|| {
+                let (sender, receiver) = mpsc::unbounded();
+                thread::spawn(move || run(&sender));
+                receiver
+            });
+            match receiver.next().await {
+                Some(event) => (event, Some(receiver)),
+                // The thread has stopped and said why already, so there is nothing left to report.
+                None => future::pending().await,
+            }
+        },
+    )
+}
+
+fn run(sender: &UnboundedSender<Event>) {
+    let mut reader = match Reader::new() {
+        Ok(reader) => reader,
+        Err(err) => {
+            error!("Could not start watching for cards: {err}");
+            let _ = sender.unbounded_send(Event::Stopped(err.to_string()));
+            return;
+        }
+    };
+    info!("Watching for cards.");
+
+    let mut readers = None;
+    loop {
+        let inserted = match reader.wait_for_insertions(POLL_TIMEOUT) {
+            Ok(inserted) => inserted,
+            Err(err) => {
+                error!("Stopped watching for cards: {err}");
+                let _ = sender.unbounded_send(Event::Stopped(err.to_string()));
+                return;
+            }
+        };
+        let current = reader.card_states();
+        if readers.as_ref() != Some(&current) {
+            readers = Some(current.clone());
+            if sender
+                .unbounded_send(Event::ReadersChanged(current))
+                .is_err()
+            {
+                return;
+            }
+        }
+        for name in inserted {
+            info!("Card inserted into {name}.");
+            // Sending only fails once the window is closed.
+            if sender.unbounded_send(Event::CardInserted(name)).is_err() {
+                return;
+            }
+        }
+    }
+}
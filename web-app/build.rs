@@ -0,0 +1,4 @@
+// Embedded migrations are only picked up on a rebuild, so rebuild whenever they change.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
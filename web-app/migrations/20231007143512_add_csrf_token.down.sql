@@ -0,0 +1 @@
+ALTER TABLE active_session DROP COLUMN csrf_token;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE active_session ADD COLUMN csrf_token varchar NOT NULL DEFAULT md5(random()::text);
+ALTER TABLE active_session ALTER COLUMN csrf_token DROP DEFAULT;
@@ -0,0 +1 @@
+DROP TABLE maintenance_attachment;
\ No newline at end of file
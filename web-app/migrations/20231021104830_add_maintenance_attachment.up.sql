@@ -0,0 +1,12 @@
+CREATE TABLE maintenance_attachment (
+	id serial4 NOT NULL,
+	maintenance_id int4 NOT NULL,
+	file_name varchar NOT NULL,
+	content_type varchar NOT NULL,
+	"size" int8 NOT NULL,
+	storage_key varchar NOT NULL,
+	uploaded_at timestamp NOT NULL DEFAULT now(),
+	CONSTRAINT maintenance_attachment_pkey PRIMARY KEY (id),
+	CONSTRAINT maintenance_attachment_storage_key_key UNIQUE (storage_key),
+	CONSTRAINT "fk-attachment-history" FOREIGN KEY (maintenance_id) REFERENCES maintenance_history(id) ON DELETE CASCADE
+);
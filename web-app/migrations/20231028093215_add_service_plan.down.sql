@@ -0,0 +1 @@
+DROP TABLE service_plan;
\ No newline at end of file
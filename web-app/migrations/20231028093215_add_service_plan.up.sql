@@ -0,0 +1,11 @@
+CREATE TABLE service_plan (
+	id serial4 NOT NULL,
+	car_id int4 NOT NULL,
+	"name" varchar NOT NULL,
+	match_subject varchar NOT NULL,
+	interval_km int4 NULL,
+	interval_months int4 NULL,
+	CONSTRAINT service_plan_pkey PRIMARY KEY (id),
+	CONSTRAINT service_plan_interval_check CHECK (interval_km > 0 OR interval_months > 0),
+	CONSTRAINT "fk-serviceplan-registration" FOREIGN KEY (car_id) REFERENCES car_registration(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+DROP TABLE expiry_notification;
+ALTER TABLE car_registration DROP COLUMN responsible_user_id;
+ALTER TABLE car_registration DROP COLUMN valid_until;
\ No newline at end of file
@@ -0,0 +1,29 @@
+ALTER TABLE car_registration ADD valid_until date NULL;
+ALTER TABLE car_registration ADD responsible_user_id int4 NULL;
+ALTER TABLE car_registration ADD CONSTRAINT "fk-registration-responsible-user" FOREIGN KEY (responsible_user_id) REFERENCES "user"(id) ON DELETE SET NULL;
+
+-- Cards store the period of validity as YYYYMMDD. Anything else, like the "Not found" of a
+-- missing tag, leaves the registration without an expiry date.
+DO $$
+DECLARE
+	r record;
+BEGIN
+	FOR r IN SELECT id, period_of_validity FROM car_registration WHERE period_of_validity ~ '^\d{8}$' LOOP
+		BEGIN
+			UPDATE car_registration SET valid_until = to_date(r.period_of_validity, 'YYYYMMDD') WHERE id = r.id;
+		EXCEPTION WHEN others THEN
+			NULL;
+		END;
+	END LOOP;
+END $$;
+
+CREATE TABLE expiry_notification (
+	id serial4 NOT NULL,
+	car_id int4 NOT NULL,
+	valid_until date NOT NULL,
+	days_before int4 NOT NULL,
+	sent_at timestamp NOT NULL DEFAULT now(),
+	CONSTRAINT expiry_notification_pkey PRIMARY KEY (id),
+	CONSTRAINT expiry_notification_unique UNIQUE (car_id, valid_until, days_before),
+	CONSTRAINT "fk-expirynotification-registration" FOREIGN KEY (car_id) REFERENCES car_registration(id) ON DELETE CASCADE
+);
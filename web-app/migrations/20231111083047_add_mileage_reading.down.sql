@@ -0,0 +1 @@
+DROP TABLE mileage_reading;
\ No newline at end of file
@@ -0,0 +1,24 @@
+CREATE TABLE mileage_reading (
+	id serial4 NOT NULL,
+	car_id int4 NOT NULL,
+	date_time timestamp NOT NULL,
+	mileage int4 NOT NULL,
+	maintenance_id int4 NULL,
+	note varchar NOT NULL DEFAULT '',
+	author_user_id int4 NULL,
+	confirmed_at timestamp NULL,
+	confirmed_by_user_id int4 NULL,
+	CONSTRAINT mileage_reading_pkey PRIMARY KEY (id),
+	CONSTRAINT mileage_reading_maintenance_unique UNIQUE (maintenance_id),
+	CONSTRAINT mileage_reading_mileage_check CHECK (mileage >= 0),
+	CONSTRAINT "fk-mileagereading-registration" FOREIGN KEY (car_id) REFERENCES car_registration(id) ON DELETE CASCADE,
+	CONSTRAINT "fk-mileagereading-maintenance" FOREIGN KEY (maintenance_id) REFERENCES maintenance_history(id) ON DELETE CASCADE,
+	CONSTRAINT "fk-mileagereading-author" FOREIGN KEY (author_user_id) REFERENCES "user"(id) ON DELETE SET NULL,
+	CONSTRAINT "fk-mileagereading-confirmedby" FOREIGN KEY (confirmed_by_user_id) REFERENCES "user"(id) ON DELETE SET NULL
+);
+
+-- Every maintenance entry with a mileage is a reading.
+INSERT INTO mileage_reading (car_id, date_time, mileage, maintenance_id, author_user_id)
+SELECT car_id, date_time, mileage, id, author_user_id
+FROM maintenance_history
+WHERE mileage IS NOT NULL AND mileage >= 0;
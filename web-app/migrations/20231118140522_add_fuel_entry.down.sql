@@ -0,0 +1,3 @@
+ALTER TABLE mileage_reading DROP COLUMN fuel_entry_id;
+DROP TABLE fuel_entry;
+ALTER TABLE maintenance_history DROP COLUMN cost_cents;
\ No newline at end of file
@@ -0,0 +1,24 @@
+ALTER TABLE maintenance_history ADD cost_cents int8 NULL;
+
+CREATE TABLE fuel_entry (
+	id serial4 NOT NULL,
+	car_id int4 NOT NULL,
+	date_time timestamp NOT NULL,
+	mileage int4 NOT NULL,
+	quantity float8 NOT NULL,
+	unit varchar NOT NULL,
+	price_cents int8 NOT NULL,
+	full_tank bool NOT NULL DEFAULT true,
+	author_user_id int4 NULL,
+	CONSTRAINT fuel_entry_pkey PRIMARY KEY (id),
+	CONSTRAINT fuel_entry_quantity_check CHECK (quantity > 0),
+	CONSTRAINT fuel_entry_price_check CHECK (price_cents >= 0),
+	CONSTRAINT fuel_entry_unit_check CHECK (unit IN ('l', 'kWh', 'kg')),
+	CONSTRAINT "fk-fuelentry-registration" FOREIGN KEY (car_id) REFERENCES car_registration(id) ON DELETE CASCADE,
+	CONSTRAINT "fk-fuelentry-author" FOREIGN KEY (author_user_id) REFERENCES "user"(id) ON DELETE SET NULL
+);
+
+-- The odometer reading taken when refuelling is part of the mileage timeline.
+ALTER TABLE mileage_reading ADD fuel_entry_id int4 NULL;
+ALTER TABLE mileage_reading ADD CONSTRAINT mileage_reading_fuel_entry_unique UNIQUE (fuel_entry_id);
+ALTER TABLE mileage_reading ADD CONSTRAINT "fk-mileagereading-fuelentry" FOREIGN KEY (fuel_entry_id) REFERENCES fuel_entry(id) ON DELETE CASCADE;
@@ -0,0 +1,2 @@
+DROP TABLE work_order_task;
+DROP TABLE work_order;
\ No newline at end of file
@@ -0,0 +1,30 @@
+CREATE TABLE work_order (
+	id serial4 NOT NULL,
+	car_id int4 NOT NULL,
+	title varchar NOT NULL,
+	planned_at timestamp NOT NULL,
+	duration_minutes int4 NOT NULL DEFAULT 60,
+	mechanic_user_id int4 NULL,
+	status varchar NOT NULL DEFAULT 'planned',
+	-- The maintenance entry written when the work order was completed.
+	maintenance_id int4 NULL,
+	created_by_user_id int4 NULL,
+	CONSTRAINT work_order_pkey PRIMARY KEY (id),
+	CONSTRAINT work_order_duration_check CHECK (duration_minutes > 0),
+	CONSTRAINT work_order_status_check CHECK (status IN ('planned', 'in_progress', 'done')),
+	CONSTRAINT work_order_maintenance_unique UNIQUE (maintenance_id),
+	CONSTRAINT "fk-workorder-registration" FOREIGN KEY (car_id) REFERENCES car_registration(id) ON DELETE CASCADE,
+	CONSTRAINT "fk-workorder-mechanic" FOREIGN KEY (mechanic_user_id) REFERENCES "user"(id) ON DELETE SET NULL,
+	CONSTRAINT "fk-workorder-maintenance" FOREIGN KEY (maintenance_id) REFERENCES maintenance_history(id) ON DELETE SET NULL,
+	CONSTRAINT "fk-workorder-creator" FOREIGN KEY (created_by_user_id) REFERENCES "user"(id) ON DELETE SET NULL
+);
+CREATE INDEX work_order_planned_at_idx ON work_order (planned_at);
+
+CREATE TABLE work_order_task (
+	id serial4 NOT NULL,
+	work_order_id int4 NOT NULL,
+	description varchar NOT NULL,
+	done bool NOT NULL DEFAULT false,
+	CONSTRAINT work_order_task_pkey PRIMARY KEY (id),
+	CONSTRAINT "fk-workordertask-workorder" FOREIGN KEY (work_order_id) REFERENCES work_order(id) ON DELETE CASCADE
+);
@@ -0,0 +1 @@
+DROP TABLE calendar_feed;
\ No newline at end of file
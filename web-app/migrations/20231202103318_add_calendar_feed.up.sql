@@ -0,0 +1,8 @@
+CREATE TABLE calendar_feed (
+	user_id int4 NOT NULL,
+	token varchar NOT NULL,
+	created_at timestamp NOT NULL DEFAULT now(),
+	CONSTRAINT calendar_feed_pkey PRIMARY KEY (user_id),
+	CONSTRAINT calendar_feed_token_unique UNIQUE (token),
+	CONSTRAINT "fk-calendarfeed-user" FOREIGN KEY (user_id) REFERENCES "user"(id) ON DELETE CASCADE
+);
@@ -0,0 +1,32 @@
+CREATE TABLE webhook_subscription (
+	id serial4 NOT NULL,
+	url varchar NOT NULL,
+	description varchar NOT NULL DEFAULT '',
+	-- Key of the HMAC signature sent with every delivery.
+	secret varchar NOT NULL,
+	events varchar[] NOT NULL,
+	active bool NOT NULL DEFAULT true,
+	created_at timestamp NOT NULL DEFAULT now(),
+	CONSTRAINT webhook_subscription_pkey PRIMARY KEY (id)
+);
+
+-- The queue of outgoing calls. Rows stay after they were delivered or given up on, so they
+-- double as the delivery log.
+CREATE TABLE webhook_delivery (
+	id serial4 NOT NULL,
+	subscription_id int4 NOT NULL,
+	"event" varchar NOT NULL,
+	payload varchar NOT NULL,
+	status varchar NOT NULL DEFAULT 'pending',
+	attempts int4 NOT NULL DEFAULT 0,
+	created_at timestamp NOT NULL DEFAULT now(),
+	next_attempt_at timestamp NOT NULL DEFAULT now(),
+	last_attempt_at timestamp NULL,
+	response_status int4 NULL,
+	last_error varchar NULL,
+	delivered_at timestamp NULL,
+	CONSTRAINT webhook_delivery_pkey PRIMARY KEY (id),
+	CONSTRAINT webhook_delivery_status_check CHECK (status IN ('pending', 'delivered', 'failed')),
+	CONSTRAINT "fk-webhookdelivery-subscription" FOREIGN KEY (subscription_id) REFERENCES webhook_subscription(id) ON DELETE CASCADE
+);
+CREATE INDEX webhook_delivery_due_idx ON webhook_delivery (next_attempt_at) WHERE status = 'pending';
@@ -0,0 +1 @@
+DROP TABLE calendar_event;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- What every event of the calendar feed last looked like, so calendar apps are told through
+-- SEQUENCE and LAST-MODIFIED when an event they already have has changed.
+CREATE TABLE calendar_event (
+	uid varchar NOT NULL,
+	fingerprint varchar NOT NULL,
+	"sequence" int4 NOT NULL DEFAULT 0,
+	modified_at timestamptz NOT NULL DEFAULT now(),
+	CONSTRAINT calendar_event_pkey PRIMARY KEY (uid)
+);
@@ -14,6 +14,7 @@ use sqlx::Either;
 use sqlx::Pool;
 use sqlx::Postgres;
 
+use crate::config::Config;
 use crate::database::create_token;
 use crate::{
     database::{self, entities::user, get_user_by_email, get_user_by_token},
@@ -21,6 +22,9 @@ use crate::{
     templates::{PageRenderer, Webpage},
 };
 
+/// Name of the cookie holding the session token of a logged in user.
+pub const LOGIN_COOKIE: &str = "LoginToken";
+
 pub struct Authentication {}
 
 impl Authentication {
@@ -47,7 +51,9 @@ impl Fairing for Authentication {
                 register_post,
                 login_get,
                 login_post,
-                logout
+                logout,
+                reset_calendar_feed,
+                delete_calendar_feed
             ],
         ))
     }
@@ -67,8 +73,33 @@ struct LoginForm<'r> {
 }
 
 #[get("/")]
-async fn get(_user: user::Model, renderer: PageRenderer<'_>) -> Result<Webpage, Error> {
-    renderer.account_page().await
+async fn get(
+    user: user::Model,
+    db: &State<Pool<Postgres>>,
+    mut renderer: PageRenderer<'_>,
+) -> Result<Webpage, Error> {
+    let calendar_token = database::get_calendar_token(db, user.id).await?;
+    renderer.account_page(calendar_token.as_deref()).await
+}
+
+/// Creates the calendar feed of the user, or replaces its address if the old one was shared
+/// by accident.
+#[post("/calendar-feed")]
+async fn reset_calendar_feed(
+    user: user::Model,
+    db: &State<Pool<Postgres>>,
+) -> Result<Redirect, Error> {
+    database::reset_calendar_token(db, user.id).await?;
+    Ok(Redirect::to(uri!("/account")))
+}
+
+#[post("/calendar-feed/delete")]
+async fn delete_calendar_feed(
+    user: user::Model,
+    db: &State<Pool<Postgres>>,
+) -> Result<Redirect, Error> {
+    database::delete_calendar_token(db, user.id).await?;
+    Ok(Redirect::to(uri!("/account")))
 }
 
 #[get("/register")]
@@ -82,7 +113,11 @@ async fn register_post(
     db: &State<Pool<Postgres>>,
     mut renderer: PageRenderer<'_>,
 ) -> Either<Redirect, Result<Webpage, Error>> {
-    match database::create_user(db, form.email, form.username, form.password).await {
+    let mut conn = match db.acquire().await {
+        Ok(conn) => conn,
+        Err(e) => return Either::Right(Err(e.into())),
+    };
+    match database::create_user(&mut conn, form.email, form.username, form.password).await {
         Ok(_) => Either::Left(Redirect::to("/")),
         Err(e) => Either::Right(renderer.register(Some(vec![e.to_string()])).await),
     }
@@ -98,6 +133,7 @@ async fn login_post(
     form: Form<LoginForm<'_>>,
     db: &State<Pool<Postgres>>,
     cookies: &CookieJar<'_>,
+    config: &State<Config>,
     mut renderer: PageRenderer<'_>,
 ) -> Result<Either<Redirect, Webpage>, Error> {
     let Some(user) = get_user_by_email(db, form.email).await? else {
@@ -112,8 +148,8 @@ async fn login_post(
         )
         .is_ok()
     {
-        let token = create_token(db, user.id).await?;
-        cookies.add(Cookie::build("LoginToken", token.token).finish());
+        let token = create_token(db, user.id, &config.session).await?;
+        cookies.add(config.cookies.apply(Cookie::new(LOGIN_COOKIE, token.token)));
         Ok(Either::Left(Redirect::to(uri!("/"))))
     } else {
         Ok(Either::Right(
@@ -124,9 +160,9 @@ async fn login_post(
     }
 }
 
-#[get("/logout")]
+#[post("/logout")]
 fn logout(cookies: &CookieJar<'_>) -> Redirect {
-    cookies.remove(Cookie::named("LoginToken"));
+    cookies.remove(Cookie::named(LOGIN_COOKIE));
     Redirect::to("/")
 }
 
@@ -135,7 +171,7 @@ impl<'r> FromRequest<'r> for user::Model {
     type Error = crate::error::Error;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let Some(cookie) = req.cookies().get("LoginToken") else {
+        let Some(cookie) = req.cookies().get(LOGIN_COOKIE) else {
             return Outcome::Failure((Status::Unauthorized, Error::UserNotLoggedIn));
         };
         let Some(db) = req.rocket().state::<Pool<Postgres>>() else {
@@ -0,0 +1,343 @@
+use std::{
+    fs,
+    io::{self, BufRead, IsTerminal},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    config::{self, Config},
+    database::{self, entities::user::Role, migrations},
+    error::Error,
+    expiry, notify,
+    transfer::{self, Format},
+};
+
+/// Exit code of `--check-migrations` when migrations are waiting to be applied.
+const EXIT_PENDING_MIGRATIONS: u8 = 2;
+
+/// The Vehikular web server. Without any options the server is started.
+#[derive(Debug, Parser)]
+pub struct Arguments {
+    /// Apply pending database migrations and exit instead of starting the server.
+    #[arg(long, conflicts_with = "check_migrations")]
+    pub migrate_only: bool,
+    /// List pending database migrations without applying them. Exits with 2 if there are any.
+    #[arg(long)]
+    pub check_migrations: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Administrative tasks that run against the configured database instead of starting the server.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Manage user accounts.
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Manage login sessions.
+    #[command(subcommand)]
+    Session(SessionCommand),
+    /// Move vehicle registrations in and out of the database.
+    #[command(subcommand)]
+    Vehicle(VehicleCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UserCommand {
+    /// Create a new user. The password is asked for unless given.
+    Create {
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        display_name: String,
+        #[arg(long)]
+        password: Option<String>,
+        #[arg(long, value_enum, default_value_t = Role::User)]
+        role: Role,
+    },
+    /// Set the password of a user and log them out everywhere. The password is asked for unless given.
+    SetPassword {
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Change the role of a user.
+    SetRole {
+        #[arg(long)]
+        email: String,
+        #[arg(long, value_enum)]
+        role: Role,
+    },
+    /// Delete a user and all of their sessions.
+    Delete {
+        #[arg(long)]
+        email: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionCommand {
+    /// Delete expired login sessions.
+    Purge {
+        /// Delete every session, logging out all users.
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VehicleCommand {
+    /// Import vehicles with their notes and maintenance history from a CSV or JSON file.
+    /// Vehicles that fail validation or already exist are reported and skipped.
+    Import(ImportArgs),
+    /// Export vehicles with their notes and maintenance history.
+    Export(ExportArgs),
+    /// Send the expiry reminders that are due, like the daily check of the server does.
+    Remind,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// The file to read from.
+    pub file: PathBuf,
+    /// The format of the file. Guessed from the file extension if not given.
+    #[arg(long, value_enum)]
+    pub format: Option<Format>,
+    /// Only validate the file and report what would be imported.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// The file to write to. Writes to stdout if not given.
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    pub format: Format,
+    /// Only export vehicles matching this search, like the search bar of the web app.
+    #[arg(long)]
+    pub query: Option<String>,
+}
+
+impl Arguments {
+    /// Whether these arguments ask for the server to be started.
+    pub fn starts_server(&self) -> bool {
+        !(self.migrate_only || self.check_migrations || self.command.is_some())
+    }
+}
+
+/// Runs the command line mode requested by the arguments, using the same configuration as the server.
+pub async fn run(args: Arguments) -> ExitCode {
+    let config = match Config::from_figment(&config::figment()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration. {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let db = match database::connect(&config).await {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Could not establish connection to database: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if args.migrate_only {
+        return match migrations::run(&db).await {
+            Ok(()) => {
+                println!("All migrations have been applied.");
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Could not apply database migrations: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    if args.check_migrations {
+        return match migrations::pending(&db).await {
+            Ok(pending) if pending.is_empty() => {
+                println!("The database is up to date.");
+                ExitCode::SUCCESS
+            }
+            Ok(pending) => {
+                for migration in pending {
+                    println!("Pending: {} {}", migration.version, migration.description);
+                }
+                ExitCode::from(EXIT_PENDING_MIGRATIONS)
+            }
+            Err(e) => {
+                eprintln!("Could not read applied migrations: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let Some(command) = args.command else {
+        return ExitCode::SUCCESS;
+    };
+
+    // Commands work on the current schema, so bring it up to date first like the server would.
+    if config.run_migrations {
+        if let Err(e) = migrations::run(&db).await {
+            eprintln!("Could not apply database migrations: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let result = match command {
+        Command::User(command) => user(&db, command).await,
+        Command::Session(command) => session(&db, command).await,
+        Command::Vehicle(command) => vehicle(&db, &config, command).await,
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn user(db: &Pool<Postgres>, command: UserCommand) -> Result<(), Error> {
+    match command {
+        UserCommand::Create {
+            email,
+            display_name,
+            password,
+            role,
+        } => {
+            let password = password_or_prompt(password)?;
+            // A user that can not get their role would be left behind with the default one.
+            let mut trans = db.begin().await?;
+            let id = database::create_user(&mut trans, &email, &display_name, &password).await?;
+            database::set_role(&mut *trans, id, role).await?;
+            trans.commit().await?;
+            println!("Created {role} '{display_name}' with id {id}.");
+        }
+        UserCommand::SetPassword { email, password } => {
+            let user = find_user(db, &email).await?;
+            let password = password_or_prompt(password)?;
+            database::set_password(db, user.id, &password).await?;
+            println!("Changed the password of '{}'.", user.display_name);
+        }
+        UserCommand::SetRole { email, role } => {
+            let user = find_user(db, &email).await?;
+            database::set_role(db, user.id, role).await?;
+            println!("'{}' is now a {role}.", user.display_name);
+        }
+        UserCommand::Delete { email } => {
+            let user = find_user(db, &email).await?;
+            database::delete_user(db, user.id).await?;
+            println!("Deleted '{}'.", user.display_name);
+        }
+    }
+    Ok(())
+}
+
+async fn session(db: &Pool<Postgres>, command: SessionCommand) -> Result<(), Error> {
+    match command {
+        SessionCommand::Purge { all } => {
+            let purged = database::purge_sessions(db, all).await?;
+            println!("Deleted {purged} sessions.");
+        }
+    }
+    Ok(())
+}
+
+async fn vehicle(
+    db: &Pool<Postgres>,
+    config: &Config,
+    command: VehicleCommand,
+) -> Result<(), Error> {
+    match command {
+        VehicleCommand::Import(args) => {
+            let format = args.format.unwrap_or_else(|| {
+                match args.file.extension().and_then(|e| e.to_str()) {
+                    Some(e) if e.eq_ignore_ascii_case("csv") => Format::Csv,
+                    _ => Format::Json,
+                }
+            });
+            let contents = fs::read_to_string(&args.file)?;
+            let report = transfer::import(db, &contents, format, args.dry_run).await?;
+
+            if let Some(error) = &report.error {
+                return Err(Error::ImportFailed(error.clone()));
+            }
+            for row in &report.rows {
+                for error in &row.errors {
+                    eprintln!("Line {} ({}): {error}", row.line, row.registration_number);
+                }
+            }
+            if report.dry_run {
+                println!(
+                    "{} vehicles can be imported, {} have errors. Nothing has been saved.",
+                    report.imported(),
+                    report.failed()
+                );
+            } else {
+                println!(
+                    "Imported {} vehicles, {} failed.",
+                    report.imported(),
+                    report.failed()
+                );
+            }
+            if report.failed() > 0 {
+                return Err(Error::ImportFailed(format!(
+                    "{} vehicles could not be imported.",
+                    report.failed()
+                )));
+            }
+        }
+        VehicleCommand::Export(args) => {
+            let records = transfer::load(db, args.query.as_deref()).await?;
+            let contents = transfer::export(&records, args.format)?;
+            match args.output {
+                Some(path) => fs::write(path, contents)?,
+                None => print!("{contents}"),
+            }
+        }
+        VehicleCommand::Remind => {
+            let notifier = notify::from_settings(&config.notifications)?;
+            let sent = expiry::send_reminders(db, notifier.as_ref(), &config.expiry).await?;
+            println!("Sent {sent} expiry reminders.");
+        }
+    }
+    Ok(())
+}
+
+async fn find_user(
+    db: &Pool<Postgres>,
+    email: &str,
+) -> Result<database::entities::user::Model, Error> {
+    database::get_user_by_email(db, email)
+        .await?
+        .ok_or_else(|| Error::UserNotFoundEmail(email.into()))
+}
+
+/// Returns the given password, or asks for one without echoing it. When stdin is not a terminal
+/// the password is read from its first line instead, so it can be piped in.
+fn password_or_prompt(password: Option<String>) -> Result<String, Error> {
+    let password = match password {
+        Some(password) => password,
+        None if io::stdin().is_terminal() => rpassword::prompt_password("Password: ")?,
+        None => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    if password.is_empty() {
+        return Err(Error::EmptyPassword);
+    }
+    Ok(password)
+}
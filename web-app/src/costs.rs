@@ -0,0 +1,517 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    database::{
+        self as db,
+        entities::{car_registration, fuel_entry, maintenance_history, mileage_reading},
+    },
+    error::Error,
+};
+
+/// An amount of money in cents. Shown and entered with two decimals, e.g. "45.60".
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct Money(pub i64);
+
+impl Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        write!(
+            f,
+            "{sign}{}.{:02}",
+            self.0.unsigned_abs() / 100,
+            self.0.unsigned_abs() % 100
+        )
+    }
+}
+
+impl FromStr for Money {
+    type Err = &'static str;
+
+    /// Parses a non-negative amount with up to two decimals. Both '.' and ',' are accepted as
+    /// the decimal separator.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().replace(',', ".");
+        let (whole, fraction) = s.split_once('.').unwrap_or((&s, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err("the amount is empty");
+        }
+        if !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+        {
+            return Err("the amount is not a positive number");
+        }
+        if fraction.len() > 2 {
+            return Err("the amount has more than two decimals");
+        }
+        let whole: i64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| "the amount is too large")?
+        };
+        let fraction: i64 = format!("{fraction:0<2}").parse().unwrap_or(0);
+        whole
+            .checked_mul(100)
+            .and_then(|cents| cents.checked_add(fraction))
+            .map(Money)
+            .ok_or("the amount is too large")
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Number(f64),
+        }
+
+        let text = match Raw::deserialize(deserializer)? {
+            Raw::Text(text) => text,
+            Raw::Number(number) => format!("{number:.2}"),
+        };
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl std::ops::Add for Money {
+    type Output = Money;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Money(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl std::iter::Sum for Money {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Money::default(), |a, b| a + b)
+    }
+}
+
+/// What fuel or energy is measured in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, FromFormField)]
+pub enum Unit {
+    #[serde(rename = "l")]
+    #[field(value = "l")]
+    Litre,
+    #[serde(rename = "kWh")]
+    #[field(value = "kWh")]
+    KilowattHour,
+    #[serde(rename = "kg")]
+    #[field(value = "kg")]
+    Kilogram,
+}
+
+impl Unit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Litre => "l",
+            Unit::KilowattHour => "kWh",
+            Unit::Kilogram => "kg",
+        }
+    }
+
+    /// The units that fit the fuel type read from the card, the most likely one first. Plug-in
+    /// hybrids are both refuelled and charged.
+    pub fn for_fuel_type(fuel_type: &str) -> Vec<Unit> {
+        let fuel_type = fuel_type.to_lowercase();
+        let has = |words: &[&str]| words.iter().any(|word| fuel_type.contains(word));
+
+        let electric = has(&["elektr", "electr"]) || fuel_type.trim() == "e";
+        let combustion = has(&["benzin", "diesel", "petrol", "hybr"]);
+        if electric && combustion {
+            vec![Unit::Litre, Unit::KilowattHour]
+        } else if electric {
+            vec![Unit::KilowattHour]
+        } else if has(&["erdgas", "cng", "lng", "wasserstoff", "hydrogen"]) {
+            vec![Unit::Kilogram]
+        } else {
+            vec![Unit::Litre]
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "l" => Ok(Unit::Litre),
+            "kWh" => Ok(Unit::KilowattHour),
+            "kg" => Ok(Unit::Kilogram),
+            _ => Err("not a unit"),
+        }
+    }
+}
+
+/// Parses an amount of fuel or energy. Both '.' and ',' are accepted as the decimal separator.
+pub fn parse_quantity(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .replace(',', ".")
+        .parse::<f64>()
+        .ok()
+        .filter(|quantity| quantity.is_finite() && *quantity > 0.0)
+}
+
+/// The average consumption between full refuels, in units per 100 km.
+#[derive(Clone, Debug, Serialize)]
+pub struct Consumption {
+    pub unit: Unit,
+    pub per_100_km: f64,
+    /// The distance the average is taken over.
+    pub distance: i32,
+}
+
+/// What was spent on a vehicle or the fleet in one month.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MonthlySpend {
+    /// The month as YYYY-MM.
+    pub month: String,
+    pub fuel: Money,
+    pub maintenance: Money,
+    pub total: Money,
+}
+
+/// The running costs of one vehicle.
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    pub registration_number: String,
+    /// The distance between the lowest and highest odometer reading.
+    pub distance: Option<i32>,
+    pub fuel: Money,
+    pub maintenance: Money,
+    pub total: Money,
+    pub cost_per_km: Option<Money>,
+    pub consumption: Vec<Consumption>,
+    /// Newest month first.
+    pub monthly: Vec<MonthlySpend>,
+}
+
+/// The running costs of every vehicle with costs, and of the fleet as a whole.
+#[derive(Clone, Debug, Serialize)]
+pub struct FleetSummary {
+    pub vehicles: Vec<Summary>,
+    pub distance: i32,
+    pub fuel: Money,
+    pub maintenance: Money,
+    pub total: Money,
+    pub cost_per_km: Option<Money>,
+    pub monthly: Vec<MonthlySpend>,
+}
+
+/// The fuel log and running costs of one vehicle.
+#[derive(Clone, Debug, Serialize)]
+pub struct VehicleCosts {
+    /// Newest first.
+    pub entries: Vec<fuel_entry::Model>,
+    pub summary: Summary,
+    /// The units refuels can be entered in, the most likely one first.
+    pub units: Vec<Unit>,
+}
+
+/// Loads the fuel log of a vehicle and sums up its running costs.
+pub async fn for_vehicle(
+    db: &Pool<Postgres>,
+    registration: &car_registration::Model,
+) -> Result<VehicleCosts, Error> {
+    let reg_num = registration.registration_number.as_str();
+    let entries = db::get_fuel_entries(db, Some(reg_num)).await?;
+    let maintenance = db::get_maintenance_costs(db, Some(reg_num)).await?;
+    let distance = db::get_distances(db, Some(reg_num))
+        .await?
+        .first()
+        .map(|d| d.distance);
+
+    let summary = summarize(reg_num.to_string(), &entries, &maintenance, distance);
+    Ok(VehicleCosts {
+        entries: entries.into_iter().rev().collect(),
+        summary,
+        units: Unit::for_fuel_type(&registration.fuel_type),
+    })
+}
+
+/// Loads and sums up the running costs of the whole fleet.
+pub async fn for_fleet(db: &Pool<Postgres>) -> Result<FleetSummary, Error> {
+    let fuel = db::get_fuel_entries(db, None).await?;
+    let maintenance = db::get_maintenance_costs(db, None).await?;
+    let distances = db::get_distances(db, None).await?;
+    Ok(fleet(&fuel, &maintenance, &distances))
+}
+
+/// Sums up the fuel entries and maintenance costs of one vehicle.
+pub fn summarize(
+    registration_number: String,
+    fuel: &[fuel_entry::Model],
+    maintenance: &[maintenance_history::Cost],
+    distance: Option<i32>,
+) -> Summary {
+    let fuel_total: Money = fuel.iter().map(|entry| entry.price).sum();
+    let maintenance_total: Money = maintenance.iter().map(|item| item.amount).sum();
+    let total = fuel_total + maintenance_total;
+
+    Summary {
+        registration_number,
+        distance,
+        fuel: fuel_total,
+        maintenance: maintenance_total,
+        total,
+        cost_per_km: distance.and_then(|distance| per_km(total, distance)),
+        consumption: consumption(fuel),
+        monthly: monthly(fuel, maintenance),
+    }
+}
+
+/// Sums up the costs of the whole fleet. Vehicles without any costs are left out.
+pub fn fleet(
+    fuel: &[fuel_entry::Model],
+    maintenance: &[maintenance_history::Cost],
+    distances: &[mileage_reading::Distance],
+) -> FleetSummary {
+    let mut fuel_by_vehicle: BTreeMap<&str, Vec<fuel_entry::Model>> = BTreeMap::new();
+    for entry in fuel {
+        fuel_by_vehicle
+            .entry(&entry.registration_number)
+            .or_default()
+            .push(entry.clone());
+    }
+    let mut maintenance_by_vehicle: BTreeMap<&str, Vec<maintenance_history::Cost>> =
+        BTreeMap::new();
+    for item in maintenance {
+        maintenance_by_vehicle
+            .entry(&item.registration_number)
+            .or_default()
+            .push(item.clone());
+    }
+    let distances: HashMap<&str, i32> = distances
+        .iter()
+        .map(|d| (d.registration_number.as_str(), d.distance))
+        .collect();
+
+    let mut registration_numbers: Vec<&str> = fuel_by_vehicle
+        .keys()
+        .chain(maintenance_by_vehicle.keys())
+        .copied()
+        .collect();
+    registration_numbers.sort_unstable();
+    registration_numbers.dedup();
+
+    let vehicles: Vec<_> = registration_numbers
+        .into_iter()
+        .map(|reg_num| {
+            summarize(
+                reg_num.to_string(),
+                fuel_by_vehicle.get(reg_num).map_or(&[], Vec::as_slice),
+                maintenance_by_vehicle
+                    .get(reg_num)
+                    .map_or(&[], Vec::as_slice),
+                distances.get(reg_num).copied(),
+            )
+        })
+        .collect();
+
+    let total: Money = vehicles.iter().map(|v| v.total).sum();
+    // Only vehicles with a known distance count towards the cost per kilometre.
+    let (costs_with_distance, distance) = vehicles
+        .iter()
+        .filter_map(|v| v.distance.map(|distance| (v.total, distance)))
+        .fold(
+            (Money::default(), 0_i32),
+            |(money, km), (total, distance)| (money + total, km.saturating_add(distance)),
+        );
+
+    FleetSummary {
+        distance,
+        fuel: vehicles.iter().map(|v| v.fuel).sum(),
+        maintenance: vehicles.iter().map(|v| v.maintenance).sum(),
+        total,
+        cost_per_km: per_km(costs_with_distance, distance),
+        monthly: monthly(fuel, maintenance),
+        vehicles,
+    }
+}
+
+fn per_km(total: Money, distance: i32) -> Option<Money> {
+    (distance > 0).then(|| {
+        let distance = i64::from(distance);
+        Money((total.0 + distance / 2) / distance)
+    })
+}
+
+/// Averages the consumption with the full tank method: the fuel put in after a full refuel,
+/// up to and including the next full refuel, was used over the distance between the two.
+fn consumption(fuel: &[fuel_entry::Model]) -> Vec<Consumption> {
+    let mut by_unit: BTreeMap<Unit, Vec<&fuel_entry::Model>> = BTreeMap::new();
+    for entry in fuel {
+        if let Ok(unit) = entry.unit.parse() {
+            by_unit.entry(unit).or_default().push(entry);
+        }
+    }
+
+    let mut result = Vec::new();
+    for (unit, mut entries) in by_unit {
+        entries.sort_by_key(|entry| (entry.mileage, entry.date_time));
+        let mut last_full: Option<i32> = None;
+        let mut pending = 0.0;
+        let (mut quantity, mut distance) = (0.0, 0);
+        for entry in entries {
+            if last_full.is_some() {
+                pending += entry.quantity;
+            }
+            if entry.full_tank {
+                if let Some(start) = last_full.filter(|start| entry.mileage > *start) {
+                    quantity += pending;
+                    distance += entry.mileage - start;
+                }
+                last_full = Some(entry.mileage);
+                pending = 0.0;
+            }
+        }
+        if distance > 0 {
+            result.push(Consumption {
+                unit,
+                per_100_km: quantity / f64::from(distance) * 100.0,
+                distance,
+            });
+        }
+    }
+    result
+}
+
+fn monthly(
+    fuel: &[fuel_entry::Model],
+    maintenance: &[maintenance_history::Cost],
+) -> Vec<MonthlySpend> {
+    let mut months: BTreeMap<String, MonthlySpend> = BTreeMap::new();
+    for entry in fuel {
+        let month = entry.date_time.format("%Y-%m").to_string();
+        let spend = months.entry(month.clone()).or_insert_with(|| MonthlySpend {
+            month,
+            ..MonthlySpend::default()
+        });
+        spend.fuel = spend.fuel + entry.price;
+    }
+    for item in maintenance {
+        let month = item.date_time.format("%Y-%m").to_string();
+        let spend = months.entry(month.clone()).or_insert_with(|| MonthlySpend {
+            month,
+            ..MonthlySpend::default()
+        });
+        spend.maintenance = spend.maintenance + item.amount;
+    }
+    months
+        .into_values()
+        .rev()
+        .map(|spend| MonthlySpend {
+            total: spend.fuel + spend.maintenance,
+            ..spend
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn refuel(day: u32, mileage: i32, quantity: f64, full_tank: bool) -> fuel_entry::Model {
+        fuel_entry::Model {
+            id: 0,
+            registration_number: "W-1".to_string(),
+            date_time: NaiveDate::from_ymd_opt(2023, 5, day)
+                .and_then(|date| date.and_hms_opt(12, 0, 0))
+                .unwrap(),
+            mileage,
+            quantity,
+            unit: "l".to_string(),
+            price: Money(5000),
+            full_tank,
+            author: None,
+        }
+    }
+
+    #[test]
+    fn amounts_are_parsed_in_cents() {
+        assert_eq!("45.60".parse(), Ok(Money(4560)));
+        assert_eq!(" 45,6 ".parse(), Ok(Money(4560)));
+        assert_eq!("12".parse(), Ok(Money(1200)));
+        assert_eq!(".5".parse(), Ok(Money(50)));
+        assert_eq!("7.".parse(), Ok(Money(700)));
+    }
+
+    #[test]
+    fn invalid_amounts_are_rejected() {
+        assert_eq!("".parse::<Money>(), Err("the amount is empty"));
+        assert_eq!(".".parse::<Money>(), Err("the amount is empty"));
+        assert_eq!(
+            "-3".parse::<Money>(),
+            Err("the amount is not a positive number")
+        );
+        assert_eq!(
+            "1.234".parse::<Money>(),
+            Err("the amount has more than two decimals")
+        );
+        assert_eq!(
+            "99999999999999999999".parse::<Money>(),
+            Err("the amount is too large")
+        );
+    }
+
+    #[test]
+    fn amounts_are_shown_with_two_decimals() {
+        assert_eq!(Money(4560).to_string(), "45.60");
+        assert_eq!(Money(5).to_string(), "0.05");
+        assert_eq!(Money(-150).to_string(), "-1.50");
+    }
+
+    #[test]
+    fn consumption_is_taken_between_full_refuels() {
+        // The first refuel only starts the measurement, the partial one is counted with the
+        // next full refuel.
+        let fuel = [
+            refuel(1, 10_000, 40.0, true),
+            refuel(5, 10_300, 10.0, false),
+            refuel(9, 10_500, 25.0, true),
+        ];
+
+        let consumption = consumption(&fuel);
+        assert_eq!(consumption.len(), 1);
+        assert_eq!(consumption[0].unit, Unit::Litre);
+        assert_eq!(consumption[0].distance, 500);
+        assert!((consumption[0].per_100_km - 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn consumption_needs_two_full_refuels() {
+        let fuel = [
+            refuel(1, 10_000, 40.0, false),
+            refuel(5, 10_300, 10.0, true),
+        ];
+
+        assert!(consumption(&fuel).is_empty());
+    }
+
+    #[test]
+    fn cost_per_km_is_rounded_to_cents() {
+        assert_eq!(per_km(Money(1000), 3), Some(Money(333)));
+        assert_eq!(per_km(Money(1000), 6), Some(Money(167)));
+        assert_eq!(per_km(Money(1000), 0), None);
+    }
+}
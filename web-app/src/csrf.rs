@@ -0,0 +1,215 @@
+use rocket::{
+    fairing::{self, Fairing, Info, Kind},
+    http::{uri::Origin, ContentType, Cookie, Method, Status},
+    request::{FromRequest, Outcome},
+    Build, Data, Request, Rocket, State,
+};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    authentication::LOGIN_COOKIE,
+    config::Config,
+    database::{generate_token, get_session_by_token},
+    error::Error,
+};
+
+/// Name of the cookie holding the CSRF token of visitors that are not logged in.
+const CSRF_COOKIE: &str = "CsrfToken";
+/// Name of the form field carrying the token.
+const CSRF_FIELD: &str = "csrf_token";
+/// How much of a request body Rocket lets fairings look at before the route reads it.
+const PEEK_LIMIT: usize = 512;
+/// Where posts without a valid token end up.
+const MISMATCH_PATH: &str = "/csrf-mismatch";
+
+/// The synchronizer token every form post of the current session has to carry.
+///
+/// Logged in users get the token stored alongside their session. Everyone else gets one
+/// handed out through a cookie, so that the login and register forms are covered as well.
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn value(&self) -> &str {
+        &self.0
+    }
+
+    /// Checks the token submitted with a form against the one belonging to this session.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::CsrfTokenMismatch`] if the tokens do not match.
+    pub fn verify(&self, submitted: &str) -> Result<(), Error> {
+        if constant_time_eq(self.0.as_bytes(), submitted.as_bytes()) {
+            Ok(())
+        } else {
+            Err(Error::CsrfTokenMismatch)
+        }
+    }
+}
+
+/// Compares two byte strings without bailing out on the first differing byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() || a.is_empty() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfToken {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(db) = req.rocket().state::<Pool<Postgres>>() else {
+            return Outcome::Failure((Status::InternalServerError, Error::DatabaseNotFound))
+        };
+
+        if let Some(cookie) = req.cookies().get(LOGIN_COOKIE) {
+            match get_session_by_token(db, cookie.value()).await {
+                Ok(Some(session)) => return Outcome::Success(CsrfToken(session.csrf_token)),
+                Ok(None) => {}
+                Err(e) => return Outcome::Failure((Status::InternalServerError, e)),
+            }
+        }
+
+        // A pending cookie means another guard already handed out a token during this request.
+        if let Some(cookie) = req.cookies().get_pending(CSRF_COOKIE) {
+            return Outcome::Success(CsrfToken(cookie.value().to_string()));
+        }
+
+        let config = match req.guard::<&State<Config>>().await {
+            Outcome::Success(config) => config,
+            Outcome::Failure(_) | Outcome::Forward(_) => {
+                return Outcome::Failure((Status::InternalServerError, Error::ConfigNotFound))
+            }
+        };
+        let token = generate_token();
+        req.cookies()
+            .add(config.cookies.apply(Cookie::new(CSRF_COOKIE, token.clone())));
+
+        Outcome::Success(CsrfToken(token))
+    }
+}
+
+/// Checks the CSRF token of every post before it reaches its route, so that no handler can
+/// forget to.
+///
+/// Fairings only get to peek at the start of the body, which is why the token has to be the
+/// first field of every form, where `macros::formatt` puts it. JSON bodies can not be sent by a
+/// form on another site and are left alone. Posts without a valid token are sent to a route that
+/// answers with [`Error::CsrfTokenMismatch`] instead.
+pub struct CsrfProtection {}
+
+impl CsrfProtection {
+    pub(crate) fn fairing() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for CsrfProtection {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF protection",
+            kind: Kind::Ignite | Kind::Request | Kind::Singleton,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.mount("/", routes![mismatch]))
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, data: &mut Data<'_>) {
+        let is_json = req
+            .content_type()
+            .is_some_and(|content_type| content_type.is_json());
+        if req.method() != Method::Post || is_json {
+            return;
+        }
+
+        let body = data.peek(PEEK_LIMIT).await;
+        let valid = match (
+            submitted_token(req.content_type(), body),
+            req.guard::<CsrfToken>().await,
+        ) {
+            (Some(submitted), Outcome::Success(csrf)) => csrf.verify(submitted).is_ok(),
+            _ => false,
+        };
+        if !valid {
+            req.set_method(Method::Get);
+            req.set_uri(Origin::parse(MISMATCH_PATH).expect("the path is a valid origin"));
+        }
+    }
+}
+
+#[get("/csrf-mismatch")]
+fn mismatch() -> Error {
+    Error::CsrfTokenMismatch
+}
+
+/// Reads the token from the first field of a url encoded or multipart form.
+fn submitted_token<'a>(content_type: Option<&ContentType>, body: &'a [u8]) -> Option<&'a str> {
+    // The peeked bytes may end in the middle of a character.
+    let body = match std::str::from_utf8(body) {
+        Ok(body) => body,
+        Err(e) => std::str::from_utf8(&body[..e.valid_up_to()]).ok()?,
+    };
+
+    match content_type {
+        Some(content_type) if content_type.is_form() => {
+            let (name, value) = body.split('&').next()?.split_once('=')?;
+            (name == CSRF_FIELD).then_some(value)
+        }
+        Some(content_type) if content_type.is_form_data() => {
+            // The boundary line and the headers of the first part, then its value.
+            let (headers, rest) = body.split_once("\r\n\r\n")?;
+            if !headers.contains(&format!("name=\"{CSRF_FIELD}\"")) {
+                return None;
+            }
+            rest.split_once("\r\n").map(|(value, _)| value)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_token_of_url_encoded_forms() {
+        let token = submitted_token(
+            Some(&ContentType::Form),
+            b"csrf_token=abc123&email=a%40b.c&password=secret",
+        );
+        assert_eq!(token, Some("abc123"));
+    }
+
+    #[test]
+    fn reads_the_token_of_multipart_forms() {
+        let body = b"--boundary\r\n\
+            Content-Disposition: form-data; name=\"csrf_token\"\r\n\r\n\
+            abc123\r\n\
+            --boundary\r\n\
+            Content-Disposition: form-data; name=\"subject\"\r\n\r\n\
+            Oil change\r\n\
+            --boundary--\r\n";
+        let token = submitted_token(Some(&ContentType::FormData), body);
+        assert_eq!(token, Some("abc123"));
+    }
+
+    #[test]
+    fn ignores_tokens_that_are_not_the_first_field() {
+        let token = submitted_token(Some(&ContentType::Form), b"email=a&csrf_token=abc123");
+        assert_eq!(token, None);
+    }
+
+    #[test]
+    fn ignores_other_content_types() {
+        assert_eq!(
+            submitted_token(Some(&ContentType::Plain), b"csrf_token=abc123"),
+            None
+        );
+        assert_eq!(submitted_token(None, b"csrf_token=abc123"), None);
+    }
+}
@@ -8,4 +8,5 @@ pub struct Model {
     pub token: String,
     pub idle_timeout: NaiveDateTime,
     pub absolute_timeout: NaiveDateTime,
+    pub csrf_token: String,
 }
@@ -0,0 +1,8 @@
+use chrono::{DateTime, Utc};
+
+/// How often an event of the calendar feed has changed, and when it last did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Revision {
+    pub sequence: i32,
+    pub modified_at: DateTime<Utc>,
+}
@@ -1,4 +1,9 @@
+use chrono::NaiveDate;
 use serde::Serialize;
+use shared::data::{
+    CertificateHolder, Engine, ExhaustEmisions, Mass, MaximumTowableMass, PersonalData,
+    Registration, SeatingCapacity, Vehicle,
+};
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 pub struct Model {
@@ -35,4 +40,80 @@ pub struct Model {
     pub braked: String,
     pub unbraked: String,
     pub environmental_category: String,
+    /// The period of validity parsed into a date, if it holds one.
+    pub valid_until: Option<NaiveDate>,
+    /// The user who is reminded before the registration expires.
+    pub responsible_user_id: Option<i32>,
+}
+
+/// A registration that expires soon or has expired, with the user responsible for it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Expiring {
+    pub id: i32,
+    pub registration_number: String,
+    pub make: String,
+    pub commercial_descriptons: String,
+    pub valid_until: NaiveDate,
+    pub responsible_user_id: Option<i32>,
+    pub responsible: Option<String>,
+    pub responsible_email: Option<String>,
+}
+
+impl TryFrom<Model> for Registration {
+    type Error = shared::data::Error;
+
+    fn try_from(value: Model) -> Result<Self, Self::Error> {
+        Ok(Registration {
+            issuer_state: value.issuer_state,
+            issuer_authority: value.issuer_authority,
+            document_number: value.document_number,
+            registration_number: value.registration_number,
+            date_of_first_registration: value.date_of_first_registration,
+            personal_data: PersonalData {
+                certificate_holder: CertificateHolder {
+                    surname_or_business_name: value.surname_or_business_name,
+                    other_name_or_initials: value.other_name_or_initials,
+                    address: value.address,
+                },
+                vehicles_owner: value.vehicles_owner.try_into()?,
+            },
+            vehicle: Vehicle {
+                make: value.make,
+                vehicle_type: value.vehicle_type,
+                commercial_descriptons: value.commercial_descriptons,
+            },
+            vehicle_identification_number: value.vehicle_identification_number,
+            mass: Mass {
+                maximum_technically_permissible_laden_mass: value.maximum_technically_laden_mass,
+                maximum_permissible_laden_mass_of_the_vehicle_in_service: value
+                    .maximum_laden_mass_of_the_vehicle_in_service,
+                maximum_permissible_laden_mass_of_the_whole_vehicle_in_service: value
+                    .maximum_laden_mass_of_the_whole_vehicle_in_service,
+            },
+            vehicle_mass_with_body: value.vehicle_mass_with_body,
+            period_of_validity: value.period_of_validity,
+            date_of_registration: value.date_of_registration,
+            type_approval_number: value.type_approval_number,
+            engine: Engine {
+                capacity: value.capacity,
+                max_net_power: value.max_net_power,
+                fuel_type: value.fuel_type,
+            },
+            power_weight_ratio: value.power_weight_ratio,
+            seating_capacity: SeatingCapacity {
+                number_of_seats: value.number_of_seats,
+                nunmber_of_standing_places: value.nunmber_of_standing_places,
+            },
+            vechicle_category: value.vechicle_category,
+            maximum_towable_mass: MaximumTowableMass {
+                braked: value.braked,
+                unbraked: value.unbraked,
+            },
+            colour: value.colour,
+            maximum_speed: value.maximum_speed,
+            exhaust_emissions: ExhaustEmisions {
+                environmental_category: value.environmental_category,
+            },
+        })
+    }
 }
@@ -0,0 +1,20 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::costs::Money;
+
+/// A refuel or charge.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Model {
+    pub id: i32,
+    pub registration_number: String,
+    pub date_time: NaiveDateTime,
+    pub mileage: i32,
+    pub quantity: f64,
+    /// One of "l", "kWh" or "kg".
+    pub unit: String,
+    pub price: Money,
+    /// Whether the tank was filled up or the battery fully charged.
+    pub full_tank: bool,
+    pub author: Option<String>,
+}
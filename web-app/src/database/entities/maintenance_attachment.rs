@@ -0,0 +1,13 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Model {
+    pub id: i32,
+    pub maintenance_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub storage_key: String,
+    pub uploaded_at: NaiveDateTime,
+}
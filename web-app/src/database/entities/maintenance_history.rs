@@ -1,11 +1,23 @@
 use chrono::NaiveDateTime;
 use serde::Serialize;
 
+use crate::costs::Money;
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 pub struct Model {
+    pub id: i32,
     pub author: Option<String>,
     pub date_time: NaiveDateTime,
     pub subject: String,
     pub body: String,
     pub mileage: Option<i32>,
+    pub cost: Option<Money>,
+}
+
+/// What a maintenance entry cost.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Cost {
+    pub registration_number: String,
+    pub date_time: NaiveDateTime,
+    pub amount: Money,
 }
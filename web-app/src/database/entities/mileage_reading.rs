@@ -0,0 +1,28 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// An odometer reading, taken from a maintenance entry or entered by hand.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Model {
+    pub id: i32,
+    pub date_time: NaiveDateTime,
+    pub mileage: i32,
+    /// The maintenance entry the reading was recorded with, if any.
+    pub maintenance_id: Option<i32>,
+    /// The refuel the reading was recorded with, if any.
+    pub fuel_entry_id: Option<i32>,
+    /// The subject of that maintenance entry.
+    pub subject: Option<String>,
+    pub note: String,
+    pub author: Option<String>,
+    /// When a user confirmed a reading that is lower than an earlier one.
+    pub confirmed_at: Option<NaiveDateTime>,
+    pub confirmed_by: Option<String>,
+}
+
+/// How far a vehicle has been driven, from its lowest to its highest reading.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Distance {
+    pub registration_number: String,
+    pub distance: i32,
+}
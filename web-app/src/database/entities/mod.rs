@@ -1,6 +1,13 @@
 pub mod active_session;
+pub mod calendar_event;
 pub mod car_registration;
+pub mod fuel_entry;
+pub mod maintenance_attachment;
 pub mod maintenance_history;
 pub mod migration;
+pub mod mileage_reading;
+pub mod service_plan;
 pub mod user;
 pub mod vehicle_notes;
+pub mod webhook;
+pub mod work_order;
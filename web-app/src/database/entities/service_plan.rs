@@ -0,0 +1,19 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// A plan together with the maintenance entries its next due date is computed from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    pub id: i32,
+    pub registration_number: String,
+    pub name: String,
+    pub match_subject: String,
+    pub interval_km: Option<i32>,
+    pub interval_months: Option<i32>,
+    /// When the latest matching maintenance entry was done.
+    pub last_date_time: Option<NaiveDateTime>,
+    /// The mileage of the latest matching maintenance entry that has one.
+    pub last_mileage: Option<i32>,
+    /// The highest odometer reading of the vehicle.
+    pub current_mileage: Option<i32>,
+}
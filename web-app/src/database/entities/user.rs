@@ -1,3 +1,5 @@
+use std::{fmt::Display, str::FromStr};
+
 use serde::Serialize;
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
@@ -6,4 +8,47 @@ pub struct Model {
     pub display_name: String,
     pub email: String,
     pub password_hash: String,
+    pub role: String,
+}
+
+/// The parts of a user that can be shown to other users.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Contact {
+    pub id: i32,
+    pub display_name: String,
+    pub email: String,
+}
+
+impl Model {
+    pub fn is_admin(&self) -> bool {
+        matches!(self.role.parse(), Ok(Role::Admin))
+    }
+}
+
+/// What a user is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::User => write!(f, "user"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = shared::data::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "admin" => Ok(Role::Admin),
+            _ => Err(shared::data::Error::NotAVariant),
+        }
+    }
 }
@@ -0,0 +1,43 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// An endpoint that is called whenever one of its events happens.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Subscription {
+    pub id: i32,
+    pub url: String,
+    pub description: String,
+    pub secret: String,
+    pub events: Vec<String>,
+    pub active: bool,
+    pub created_at: NaiveDateTime,
+}
+
+/// An entry of the delivery log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Delivery {
+    pub id: i32,
+    pub subscription_id: i32,
+    pub url: String,
+    pub event: String,
+    /// One of `pending`, `delivered` or `failed`.
+    pub status: String,
+    pub attempts: i32,
+    pub created_at: NaiveDateTime,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_attempt_at: Option<NaiveDateTime>,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+/// A delivery that is due, with everything needed to send it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pending {
+    pub id: i32,
+    pub url: String,
+    pub secret: String,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i32,
+}
@@ -0,0 +1,28 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// A planned job on a vehicle.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Model {
+    pub id: i32,
+    pub registration_number: String,
+    pub title: String,
+    pub planned_at: NaiveDateTime,
+    pub duration_minutes: i32,
+    pub mechanic_user_id: Option<i32>,
+    pub mechanic: Option<String>,
+    /// One of `planned`, `in_progress` or `done`.
+    pub status: String,
+    /// The maintenance entry written when the work order was completed.
+    pub maintenance_id: Option<i32>,
+    pub created_by: Option<String>,
+}
+
+/// One item on the task list of a work order.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Task {
+    pub id: i32,
+    pub work_order_id: i32,
+    pub description: String,
+    pub done: bool,
+}
@@ -2,17 +2,16 @@ use rocket::{
     fairing::{self, Fairing, Info, Kind},
     Build, Rocket,
 };
-use sqlx::postgres::PgPoolOptions;
 
-pub struct DatabaseFairing {
-    connection_string: String,
-}
+use crate::config::Config;
+
+use super::{connect, migrations};
+
+pub struct DatabaseFairing;
 
 impl DatabaseFairing {
-    pub fn fairing(connection_string: &str) -> Self {
-        Self {
-            connection_string: connection_string.into(),
-        }
+    pub fn fairing() -> Self {
+        Self {}
     }
 }
 
@@ -26,11 +25,12 @@ impl Fairing for DatabaseFairing {
     }
 
     async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
-        let db = match PgPoolOptions::new()
-            .max_connections(5)
-            .connect(&self.connection_string)
-            .await
-        {
+        let Some(config) = rocket.state::<Config>() else {
+            error!("The database fairing requires the configuration to be loaded first.");
+            return Err(rocket);
+        };
+
+        let db = match connect(config).await {
             Ok(pool) => pool,
             Err(e) => {
                 error!("Could not establish connection to database: {e}");
@@ -38,6 +38,13 @@ impl Fairing for DatabaseFairing {
             }
         };
 
+        if config.run_migrations {
+            if let Err(e) = migrations::run(&db).await {
+                error!("Could not apply database migrations: {e}");
+                return Err(rocket);
+            }
+        }
+
         Ok(rocket.manage(db))
     }
 }
@@ -0,0 +1,45 @@
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migration, Migrator},
+    Pool, Postgres,
+};
+
+/// The migrations from the `migrations` directory, embedded at compile time.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Applies all pending migrations.
+///
+/// The migrator holds a Postgres advisory lock while it runs, so several replicas starting at
+/// the same time wait for each other instead of applying the same migration twice.
+///
+/// # Errors
+///
+/// This function will return an error if a migration fails or the applied migrations do not
+/// match the embedded ones.
+pub async fn run(db: &Pool<Postgres>) -> Result<(), MigrateError> {
+    MIGRATOR.run(db).await
+}
+
+/// Returns the embedded migrations that have not been applied to the database yet.
+///
+/// # Errors
+///
+/// This function will return an error if the applied migrations could not be read.
+pub async fn pending(db: &Pool<Postgres>) -> Result<Vec<&'static Migration>, MigrateError> {
+    let table_exists = sqlx::query_scalar!(
+        "select to_regclass('_sqlx_migrations') is not null as \"exists!\""
+    )
+    .fetch_one(db)
+    .await?;
+
+    let applied = if table_exists {
+        db.acquire().await?.list_applied_migrations().await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .filter(|migration| !applied.iter().any(|a| a.version == migration.version))
+        .collect())
+}
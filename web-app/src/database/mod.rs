@@ -4,49 +4,153 @@ use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
     Argon2, PasswordHasher,
 };
-use chrono::{Days, Duration, Local, NaiveDateTime};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime};
 use rand::{distributions::Alphanumeric, Rng};
 
 use shared::data::Registration;
-use sqlx::{Pool, Postgres};
+use sqlx::{postgres::PgPoolOptions, Connection, PgConnection, PgExecutor, Pool, Postgres};
 
-use crate::error::{Error, RegistrationError};
+use crate::{
+    config::{Config, SessionSettings},
+    costs::{Money, Unit},
+    error::{Error, RegistrationError},
+    expiry,
+    work_order::WorkOrderStatus,
+};
 
-use self::entities::{active_session, car_registration, maintenance_history, user, vehicle_notes};
+use self::entities::{
+    active_session, calendar_event, car_registration, fuel_entry, maintenance_attachment,
+    maintenance_history, mileage_reading, service_plan,
+    user::{self, Role},
+    vehicle_notes, webhook, work_order,
+};
 
 pub mod entities;
 pub mod fairing;
+pub mod migrations;
+
+/// Opens a connection pool to the database described by the configuration.
+pub async fn connect(config: &Config) -> Result<Pool<Postgres>, sqlx::Error> {
+    PgPoolOptions::new()
+        .max_connections(config.database_pool_size)
+        .connect(&config.database_url)
+        .await
+}
 
 pub async fn get_registration(
     db: &Pool<Postgres>,
     reg_num: &str,
+) -> Result<Option<car_registration::Model>, Error> {
+    find_registration(db, reg_num).await
+}
+
+/// Like [`get_registration`], for use in a transaction.
+pub async fn find_registration<'e>(
+    executor: impl PgExecutor<'e>,
+    reg_num: &str,
 ) -> Result<Option<car_registration::Model>, Error> {
     sqlx::query_as!(
         car_registration::Model,
         "SELECT * FROM car_registration cr WHERE cr.registration_number = $1",
         reg_num
     )
-    .fetch_optional(db)
+    .fetch_optional(executor)
     .await
     .map_err(Error::DbError)
 }
 
 pub async fn insert_registration(
-    db: &Pool<Postgres>,
+    conn: &mut PgConnection,
     registration: Registration,
 ) -> Result<(), Error> {
-    if get_registration(db, &registration.registration_number)
+    if find_registration(&mut *conn, &registration.registration_number)
         .await?
         .is_some()
     {
         Err(RegistrationError::AlreadyExists)?
     } else {
-        sqlx::query!("insert into car_registration (issuer_state, issuer_authority, document_number, registration_number, date_of_first_registration, vehicle_identification_number, vehicle_mass_with_body, period_of_validity, date_of_registration, type_approval_number, power_weight_ratio, vechicle_category, colour, maximum_speed, vehicles_owner, surname_or_business_name, other_name_or_initials, address, make, vehicle_type, commercial_descriptons, maximum_technically_laden_mass, maximum_laden_mass_of_the_vehicle_in_service, maximum_laden_mass_of_the_whole_vehicle_in_service, capacity, max_net_power, fuel_type, number_of_seats, nunmber_of_standing_places, braked, unbraked, environmental_category)
-        values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32)", registration.issuer_state, registration.issuer_authority, registration.document_number, registration.registration_number, registration.date_of_first_registration, registration.vehicle_identification_number, registration.vehicle_mass_with_body, registration.period_of_validity, registration.date_of_registration, registration.type_approval_number, registration.power_weight_ratio, registration.vechicle_category, registration.colour, registration.maximum_speed, registration.personal_data.vehicles_owner.to_string(), registration.personal_data.certificate_holder.surname_or_business_name, registration.personal_data.certificate_holder.other_name_or_initials, registration.personal_data.certificate_holder.address, registration.vehicle.make, registration.vehicle.vehicle_type, registration.vehicle.commercial_descriptons, registration.mass.maximum_technically_permissible_laden_mass, registration.mass.maximum_permissible_laden_mass_of_the_vehicle_in_service, registration.mass.maximum_permissible_laden_mass_of_the_whole_vehicle_in_service, registration.engine.capacity, registration.engine.max_net_power, registration.engine.fuel_type, registration.seating_capacity.number_of_seats, registration.seating_capacity.nunmber_of_standing_places, registration.maximum_towable_mass.braked, registration.maximum_towable_mass.unbraked, registration.exhaust_emissions.environmental_category).execute(db).await?;
+        insert_registration_row(&mut *conn, &registration).await?;
         Ok(())
     }
 }
 
+async fn insert_registration_row<'e>(
+    executor: impl PgExecutor<'e>,
+    registration: &Registration,
+) -> Result<i32, Error> {
+    Ok(sqlx::query_scalar!("insert into car_registration (issuer_state, issuer_authority, document_number, registration_number, date_of_first_registration, vehicle_identification_number, vehicle_mass_with_body, period_of_validity, date_of_registration, type_approval_number, power_weight_ratio, vechicle_category, colour, maximum_speed, vehicles_owner, surname_or_business_name, other_name_or_initials, address, make, vehicle_type, commercial_descriptons, maximum_technically_laden_mass, maximum_laden_mass_of_the_vehicle_in_service, maximum_laden_mass_of_the_whole_vehicle_in_service, capacity, max_net_power, fuel_type, number_of_seats, nunmber_of_standing_places, braked, unbraked, environmental_category, valid_until)
+        values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, $33)
+        returning id", registration.issuer_state, registration.issuer_authority, registration.document_number, registration.registration_number, registration.date_of_first_registration, registration.vehicle_identification_number, registration.vehicle_mass_with_body, registration.period_of_validity, registration.date_of_registration, registration.type_approval_number, registration.power_weight_ratio, registration.vechicle_category, registration.colour, registration.maximum_speed, registration.personal_data.vehicles_owner.to_string(), registration.personal_data.certificate_holder.surname_or_business_name, registration.personal_data.certificate_holder.other_name_or_initials, registration.personal_data.certificate_holder.address, registration.vehicle.make, registration.vehicle.vehicle_type, registration.vehicle.commercial_descriptons, registration.mass.maximum_technically_permissible_laden_mass, registration.mass.maximum_permissible_laden_mass_of_the_vehicle_in_service, registration.mass.maximum_permissible_laden_mass_of_the_whole_vehicle_in_service, registration.engine.capacity, registration.engine.max_net_power, registration.engine.fuel_type, registration.seating_capacity.number_of_seats, registration.seating_capacity.nunmber_of_standing_places, registration.maximum_towable_mass.braked, registration.maximum_towable_mass.unbraked, registration.exhaust_emissions.environmental_category, expiry::parse_validity(&registration.period_of_validity)).fetch_one(executor).await?)
+}
+
+/// A maintenance entry that is about to be written to the database.
+pub struct NewMaintenanceItem<'a> {
+    pub date_time: NaiveDateTime,
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub mileage: Option<i32>,
+    pub cost: Option<Money>,
+    pub author_user_id: Option<i32>,
+}
+
+/// Inserts a registration together with its notes and maintenance history. Either all of it
+/// is written or nothing is.
+pub async fn import_vehicle(
+    conn: &mut PgConnection,
+    registration: &Registration,
+    notes: Option<&str>,
+    history: &[NewMaintenanceItem<'_>],
+) -> Result<(), Error> {
+    let mut trans = conn.begin().await?;
+
+    let car_id = insert_registration_row(&mut *trans, registration).await?;
+    if let Some(notes) = notes {
+        sqlx::query!(
+            "insert into vehicle_notes (car_id, body)
+            values ($1, $2)",
+            car_id,
+            notes
+        )
+        .execute(&mut *trans)
+        .await?;
+    }
+    for item in history {
+        let maintenance_id = sqlx::query_scalar!(
+            "insert into maintenance_history (car_id, date_time, subject, body, mileage, author_user_id, cost_cents)
+             values ($1, $2, $3, $4, $5, $6, $7)
+             returning id",
+            car_id,
+            item.date_time,
+            item.subject,
+            item.body,
+            item.mileage,
+            item.author_user_id,
+            item.cost.map(|cost| cost.0)
+        )
+        .fetch_one(&mut *trans)
+        .await?;
+        if let Some(mileage) = item.mileage {
+            insert_mileage_reading_row(
+                &mut *trans,
+                car_id,
+                &NewMileageReading {
+                    date_time: item.date_time,
+                    mileage,
+                    maintenance_id: Some(maintenance_id),
+                    fuel_entry_id: None,
+                    note: "",
+                    author_user_id: item.author_user_id,
+                    confirmed_by_user_id: None,
+                },
+            )
+            .await?;
+        }
+    }
+
+    trans.commit().await?;
+    Ok(())
+}
+
 pub async fn get_all_registrations(
     db: &Pool<Postgres>,
 ) -> Result<Vec<car_registration::Model>, Error> {
@@ -56,6 +160,40 @@ pub async fn get_all_registrations(
         .map_err(Error::DbError)
 }
 
+/// Returns the registrations where the registration number, holder, make, description or
+/// vehicle identification number contain the query. Returns all registrations without a query.
+pub async fn search_registrations(
+    db: &Pool<Postgres>,
+    query: Option<&str>,
+) -> Result<Vec<car_registration::Model>, Error> {
+    let Some(query) = query.map(str::trim).filter(|q| !q.is_empty()) else {
+        return get_all_registrations(db).await;
+    };
+    let pattern = format!(
+        "%{}%",
+        query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+
+    sqlx::query_as!(
+        car_registration::Model,
+        "select * from car_registration cr
+        where cr.registration_number ilike $1
+            or cr.surname_or_business_name ilike $1
+            or cr.other_name_or_initials ilike $1
+            or cr.make ilike $1
+            or cr.commercial_descriptons ilike $1
+            or cr.vehicle_identification_number ilike $1
+        order by cr.registration_number",
+        pattern
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
 pub async fn get_registration_with_history_and_notes(
     db: &Pool<Postgres>,
     reg_num: &str,
@@ -89,7 +227,7 @@ pub async fn get_registration_with_history_and_notes(
 
     let history = sqlx::query_as!(
         maintenance_history::Model,
-        "SELECT mh.date_time as \"date_time!\", mh.subject as \"subject!\", mh.body as \"body!\", mh.mileage as \"mileage!\", u.display_name as \"author?\"
+        "SELECT mh.id as \"id!\", mh.date_time as \"date_time!\", mh.subject as \"subject!\", mh.body as \"body!\", mh.mileage as \"mileage!\", u.display_name as \"author?\", mh.cost_cents as \"cost: Money\"
         FROM maintenance_history mh
         LEFT JOIN \"user\" u ON mh.author_user_id = u.id  
         WHERE mh.car_id = (SELECT id FROM car_registration cr WHERE cr.registration_number = $1);
@@ -108,7 +246,7 @@ struct UpdateInsertNote {
 }
 
 pub async fn update_or_insert_notes(
-    db: &Pool<Postgres>,
+    conn: &mut PgConnection,
     reg_num: &str,
     notes: &str,
 ) -> Result<(), Error> {
@@ -120,7 +258,7 @@ pub async fn update_or_insert_notes(
         where cr.registration_number = $1",
         reg_num
     )
-    .fetch_optional(db)
+    .fetch_optional(&mut *conn)
     .await? else {
         return Err(Error::RegistrationNotFound(reg_num.to_string()))
     };
@@ -133,7 +271,7 @@ pub async fn update_or_insert_notes(
             notes,
             notes_id
         )
-        .execute(db)
+        .execute(&mut *conn)
         .await?;
     } else {
         // Insert
@@ -143,141 +281,1376 @@ pub async fn update_or_insert_notes(
             query.car_id,
             notes
         )
-        .execute(db)
+        .execute(&mut *conn)
         .await?;
     }
 
     Ok(())
 }
 
+/// Inserts a maintenance entry with its mileage reading and the attachments that have already
+/// been stored. Either all of it is written or nothing is. If the author has confirmed a mileage
+/// that is out of order, the reading is stored as confirmed by them.
 pub async fn insert_maintenance_item(
+    conn: &mut PgConnection,
+    car_id: i32,
+    item: &NewMaintenanceItem<'_>,
+    mileage_confirmed: bool,
+    attachments: &[NewAttachment<'_>],
+) -> Result<i32, Error> {
+    let mut trans = conn.begin().await?;
+    let id = insert_maintenance_item_row(&mut trans, car_id, item, mileage_confirmed).await?;
+    insert_attachment_rows(&mut trans, id, attachments).await?;
+    trans.commit().await?;
+    Ok(id)
+}
+
+async fn insert_maintenance_item_row(
+    conn: &mut PgConnection,
+    car_id: i32,
+    item: &NewMaintenanceItem<'_>,
+    mileage_confirmed: bool,
+) -> Result<i32, Error> {
+    let id = sqlx::query_scalar!(
+        "insert into maintenance_history (car_id, date_time, subject, body, mileage, author_user_id, cost_cents)
+         values ($1, $2, $3, $4, $5, $6, $7)
+         returning id",
+        car_id,
+        item.date_time,
+        item.subject,
+        item.body,
+        item.mileage,
+        item.author_user_id,
+        item.cost.map(|cost| cost.0)
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if let Some(mileage) = item.mileage {
+        insert_mileage_reading_row(
+            &mut *conn,
+            car_id,
+            &NewMileageReading {
+                date_time: item.date_time,
+                mileage,
+                maintenance_id: Some(id),
+                fuel_entry_id: None,
+                note: "",
+                author_user_id: item.author_user_id,
+                confirmed_by_user_id: item.author_user_id.filter(|_| mileage_confirmed),
+            },
+        )
+        .await?;
+    }
+
+    Ok(id)
+}
+
+/// An odometer reading that is about to be written to the database.
+pub struct NewMileageReading<'a> {
+    pub date_time: NaiveDateTime,
+    pub mileage: i32,
+    pub maintenance_id: Option<i32>,
+    pub fuel_entry_id: Option<i32>,
+    pub note: &'a str,
+    pub author_user_id: Option<i32>,
+    /// Set when the author confirmed a mileage that is out of order.
+    pub confirmed_by_user_id: Option<i32>,
+}
+
+pub async fn insert_mileage_reading(
     db: &Pool<Postgres>,
     car_id: i32,
-    date_time: NaiveDateTime,
-    subject: &str,
-    body: &str,
-    mileage: i32,
-    author_user_id: i32,
+    reading: &NewMileageReading<'_>,
+) -> Result<(), Error> {
+    insert_mileage_reading_row(db, car_id, reading).await
+}
+
+async fn insert_mileage_reading_row<'e>(
+    executor: impl PgExecutor<'e>,
+    car_id: i32,
+    reading: &NewMileageReading<'_>,
 ) -> Result<(), Error> {
     sqlx::query!(
-        "insert into maintenance_history (car_id, date_time, subject, body, mileage, author_user_id)
-         values ($1, $2, $3, $4, $5, $6)",
+        "insert into mileage_reading (car_id, date_time, mileage, maintenance_id, fuel_entry_id, note, author_user_id, confirmed_at, confirmed_by_user_id)
+        values ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
         car_id,
-        date_time,
-        subject.into(),
-        body.into(),
-        mileage,
-        author_user_id
+        reading.date_time,
+        reading.mileage,
+        reading.maintenance_id,
+        reading.fuel_entry_id,
+        reading.note,
+        reading.author_user_id,
+        reading
+            .confirmed_by_user_id
+            .map(|_| Local::now().naive_local()),
+        reading.confirmed_by_user_id
     )
-    .execute(db)
+    .execute(executor)
     .await?;
     Ok(())
 }
 
-pub async fn create_user(
+/// Returns the odometer readings of a registration, oldest first.
+pub async fn get_mileage_readings(
     db: &Pool<Postgres>,
-    email: &str,
-    display_name: &str,
-    password: &str,
-) -> Result<(), Error> {
-    if sqlx::query_as!(
-        user::Model,
-        "select * from \"user\" u
-        where u.email = $1 or u.display_name = $2
-        limit 1",
-        email,
-        display_name
+    reg_num: &str,
+) -> Result<Vec<mileage_reading::Model>, Error> {
+    sqlx::query_as!(
+        mileage_reading::Model,
+        "select mr.id, mr.date_time, mr.mileage, mr.maintenance_id, mr.fuel_entry_id, mh.subject as \"subject?\", mr.note,
+            author.display_name as \"author?\", mr.confirmed_at, confirmer.display_name as \"confirmed_by?\"
+        from mileage_reading mr
+        inner join car_registration cr on cr.id = mr.car_id
+        left join maintenance_history mh on mh.id = mr.maintenance_id
+        left join \"user\" author on author.id = mr.author_user_id
+        left join \"user\" confirmer on confirmer.id = mr.confirmed_by_user_id
+        where cr.registration_number = $1
+        order by mr.date_time, mr.id",
+        reg_num
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Marks a reading as checked by a user, which silences the warning about it being lower than
+/// an earlier one. Returns the registration number of the vehicle it belongs to.
+pub async fn confirm_mileage_reading(
+    db: &Pool<Postgres>,
+    id: i32,
+    user_id: i32,
+) -> Result<String, Error> {
+    sqlx::query_scalar!(
+        "update mileage_reading mr
+        set confirmed_at = $2, confirmed_by_user_id = $3
+        from car_registration cr
+        where mr.id = $1 and cr.id = mr.car_id
+        returning cr.registration_number",
+        id,
+        Local::now().naive_local(),
+        user_id
     )
     .fetch_optional(db)
     .await?
-    .is_some()
-    {
-        return Err(Error::RegistrationError(RegistrationError::AlreadyExists));
-    }
+    .ok_or(Error::MileageReadingNotFound(id))
+}
 
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
+/// Deletes a reading that was entered by hand. Readings of maintenance entries and refuels go
+/// away with them. Returns the registration number of the vehicle it belonged to.
+pub async fn delete_mileage_reading(db: &Pool<Postgres>, id: i32) -> Result<String, Error> {
+    sqlx::query_scalar!(
+        "delete from mileage_reading mr
+        using car_registration cr
+        where mr.id = $1 and mr.maintenance_id is null and mr.fuel_entry_id is null and cr.id = mr.car_id
+        returning cr.registration_number",
+        id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(Error::MileageReadingNotFound(id))
+}
 
-    let password_hash = argon2
-        .hash_password(password.as_bytes(), &salt)?
-        .to_string();
+/// Deletes a maintenance entry and its attachments. Returns the registration number it
+/// belonged to and the storage keys of its attachments, so the files can be removed as well.
+pub async fn delete_maintenance_item(
+    conn: &mut PgConnection,
+    id: i32,
+) -> Result<(String, Vec<String>), Error> {
+    let mut trans = conn.begin().await?;
 
-    sqlx::query!(
-        "insert into \"user\" (email, display_name, password_hash)
-                  values ($1, $2, $3)",
-        email.into(),
-        display_name.into(),
-        password_hash
+    let keys = sqlx::query_scalar!(
+        "delete from maintenance_attachment where maintenance_id = $1 returning storage_key",
+        id
     )
-    .execute(db)
+    .fetch_all(&mut *trans)
     .await?;
 
+    let registration_number = sqlx::query_scalar!(
+        "delete from maintenance_history mh
+        using car_registration cr
+        where mh.id = $1 and cr.id = mh.car_id
+        returning cr.registration_number",
+        id
+    )
+    .fetch_optional(&mut *trans)
+    .await?
+    .ok_or(Error::MaintenanceItemNotFound(id))?;
+
+    trans.commit().await?;
+    Ok((registration_number, keys))
+}
+
+/// A file that has been stored and is about to be linked to a maintenance entry.
+pub struct NewAttachment<'a> {
+    pub file_name: &'a str,
+    pub content_type: &'a str,
+    pub size: i64,
+    pub storage_key: &'a str,
+}
+
+async fn insert_attachment_rows(
+    conn: &mut PgConnection,
+    maintenance_id: i32,
+    attachments: &[NewAttachment<'_>],
+) -> Result<(), Error> {
+    for attachment in attachments {
+        sqlx::query!(
+            "insert into maintenance_attachment (maintenance_id, file_name, content_type, \"size\", storage_key)
+            values ($1, $2, $3, $4, $5)",
+            maintenance_id,
+            attachment.file_name,
+            attachment.content_type,
+            attachment.size,
+            attachment.storage_key
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
     Ok(())
 }
 
-pub async fn delete_user(db: &Pool<Postgres>, user_id: i32) -> Result<(), Error> {
-    let mut trans = db.begin().await?;
+/// Returns the attachments of all maintenance entries of a registration.
+pub async fn get_attachments(
+    db: &Pool<Postgres>,
+    reg_num: &str,
+) -> Result<Vec<maintenance_attachment::Model>, Error> {
+    sqlx::query_as!(
+        maintenance_attachment::Model,
+        "select ma.* from maintenance_attachment ma
+        inner join maintenance_history mh on mh.id = ma.maintenance_id
+        inner join car_registration cr on cr.id = mh.car_id
+        where cr.registration_number = $1
+        order by ma.id",
+        reg_num
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
 
-    sqlx::query!("delete from active_session where user_id = $1", user_id)
+/// Returns the attachment if it belongs to a maintenance entry of the registration.
+pub async fn get_attachment(
+    db: &Pool<Postgres>,
+    reg_num: &str,
+    id: i32,
+) -> Result<Option<maintenance_attachment::Model>, Error> {
+    sqlx::query_as!(
+        maintenance_attachment::Model,
+        "select ma.* from maintenance_attachment ma
+        inner join maintenance_history mh on mh.id = ma.maintenance_id
+        inner join car_registration cr on cr.id = mh.car_id
+        where cr.registration_number = $1 and ma.id = $2",
+        reg_num,
+        id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Deletes a registration with its notes, maintenance history and attachments. Returns the
+/// storage keys of the attachments, so the files can be removed as well.
+pub async fn delete_registration(
+    conn: &mut PgConnection,
+    reg_num: &str,
+) -> Result<Vec<String>, Error> {
+    let mut trans = conn.begin().await?;
+
+    let car_id = sqlx::query_scalar!(
+        "select id from car_registration where registration_number = $1",
+        reg_num
+    )
+    .fetch_optional(&mut *trans)
+    .await?
+    .ok_or_else(|| Error::RegistrationNotFound(reg_num.into()))?;
+
+    let keys = sqlx::query_scalar!(
+        "delete from maintenance_attachment ma
+        using maintenance_history mh
+        where mh.id = ma.maintenance_id and mh.car_id = $1
+        returning ma.storage_key",
+        car_id
+    )
+    .fetch_all(&mut *trans)
+    .await?;
+    sqlx::query!("delete from maintenance_history where car_id = $1", car_id)
         .execute(&mut *trans)
         .await?;
-    sqlx::query!("delete from \"user\" where id = $1", user_id)
+    sqlx::query!("delete from vehicle_notes where car_id = $1", car_id)
+        .execute(&mut *trans)
+        .await?;
+    sqlx::query!("delete from car_registration where id = $1", car_id)
         .execute(&mut *trans)
         .await?;
 
     trans.commit().await?;
+    Ok(keys)
+}
+
+pub async fn insert_service_plan(
+    db: &Pool<Postgres>,
+    car_id: i32,
+    name: &str,
+    match_subject: &str,
+    interval_km: Option<i32>,
+    interval_months: Option<i32>,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "insert into service_plan (car_id, \"name\", match_subject, interval_km, interval_months)
+        values ($1, $2, $3, $4, $5)",
+        car_id,
+        name,
+        match_subject,
+        interval_km,
+        interval_months
+    )
+    .execute(db)
+    .await?;
     Ok(())
 }
 
-pub async fn get_user_by_email(
+/// Deletes a service plan and returns the registration number of the vehicle it belonged to.
+pub async fn delete_service_plan(db: &Pool<Postgres>, id: i32) -> Result<String, Error> {
+    sqlx::query_scalar!(
+        "delete from service_plan sp
+        using car_registration cr
+        where sp.id = $1 and cr.id = sp.car_id
+        returning cr.registration_number",
+        id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(Error::ServicePlanNotFound(id))
+}
+
+/// Returns the service plans of one vehicle, or of the whole fleet without a registration
+/// number, together with the latest maintenance entries whose subject contains the plan's
+/// `match_subject`.
+pub async fn get_service_progress(
     db: &Pool<Postgres>,
-    email: &str,
-) -> Result<Option<user::Model>, Error> {
+    reg_num: Option<&str>,
+) -> Result<Vec<service_plan::Progress>, Error> {
     sqlx::query_as!(
-        user::Model,
-        "select * from \"user\" where email = $1",
-        email
+        service_plan::Progress,
+        "select sp.id, cr.registration_number, sp.\"name\", sp.match_subject, sp.interval_km, sp.interval_months,
+            last_done.date_time as \"last_date_time?\", last_mileage.mileage as \"last_mileage?\", current.mileage as \"current_mileage?\"
+        from service_plan sp
+        inner join car_registration cr on cr.id = sp.car_id
+        left join lateral (
+            select mh.date_time from maintenance_history mh
+            where mh.car_id = sp.car_id and strpos(lower(mh.subject), lower(sp.match_subject)) > 0
+            order by mh.date_time desc limit 1
+        ) last_done on true
+        left join lateral (
+            select mh.mileage from maintenance_history mh
+            where mh.car_id = sp.car_id and strpos(lower(mh.subject), lower(sp.match_subject)) > 0
+                and mh.mileage is not null
+            order by mh.date_time desc limit 1
+        ) last_mileage on true
+        left join lateral (
+            select max(mr.mileage) as mileage from mileage_reading mr where mr.car_id = sp.car_id
+        ) current on true
+        where $1::varchar is null or cr.registration_number = $1
+        order by cr.registration_number, sp.\"name\"",
+        reg_num
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// A refuel or charge that is about to be written to the database.
+pub struct NewFuelEntry {
+    pub date_time: NaiveDateTime,
+    pub mileage: i32,
+    pub quantity: f64,
+    pub unit: Unit,
+    pub price: Money,
+    pub full_tank: bool,
+    pub author_user_id: i32,
+}
+
+/// Inserts a refuel and its mileage reading. If the author has confirmed a mileage that is out
+/// of order, the reading is stored as confirmed by them.
+pub async fn insert_fuel_entry(
+    db: &Pool<Postgres>,
+    car_id: i32,
+    entry: &NewFuelEntry,
+    mileage_confirmed: bool,
+) -> Result<(), Error> {
+    let mut trans = db.begin().await?;
+
+    let id = sqlx::query_scalar!(
+        "insert into fuel_entry (car_id, date_time, mileage, quantity, unit, price_cents, full_tank, author_user_id)
+        values ($1, $2, $3, $4, $5, $6, $7, $8)
+        returning id",
+        car_id,
+        entry.date_time,
+        entry.mileage,
+        entry.quantity,
+        entry.unit.symbol(),
+        entry.price.0,
+        entry.full_tank,
+        entry.author_user_id
+    )
+    .fetch_one(&mut *trans)
+    .await?;
+    insert_mileage_reading_row(
+        &mut *trans,
+        car_id,
+        &NewMileageReading {
+            date_time: entry.date_time,
+            mileage: entry.mileage,
+            maintenance_id: None,
+            fuel_entry_id: Some(id),
+            note: "",
+            author_user_id: Some(entry.author_user_id),
+            confirmed_by_user_id: Some(entry.author_user_id).filter(|_| mileage_confirmed),
+        },
+    )
+    .await?;
+
+    trans.commit().await?;
+    Ok(())
+}
+
+/// Deletes a refuel with its mileage reading and returns the registration number of the vehicle
+/// it belonged to.
+pub async fn delete_fuel_entry(db: &Pool<Postgres>, id: i32) -> Result<String, Error> {
+    sqlx::query_scalar!(
+        "delete from fuel_entry fe
+        using car_registration cr
+        where fe.id = $1 and cr.id = fe.car_id
+        returning cr.registration_number",
+        id
     )
     .fetch_optional(db)
+    .await?
+    .ok_or(Error::FuelEntryNotFound(id))
+}
+
+/// Returns the refuels of one vehicle, or of the whole fleet without a registration number,
+/// oldest first.
+pub async fn get_fuel_entries(
+    db: &Pool<Postgres>,
+    reg_num: Option<&str>,
+) -> Result<Vec<fuel_entry::Model>, Error> {
+    sqlx::query_as!(
+        fuel_entry::Model,
+        "select fe.id, cr.registration_number, fe.date_time, fe.mileage, fe.quantity, fe.unit,
+            fe.price_cents as \"price: Money\", fe.full_tank, u.display_name as \"author?\"
+        from fuel_entry fe
+        inner join car_registration cr on cr.id = fe.car_id
+        left join \"user\" u on u.id = fe.author_user_id
+        where $1::varchar is null or cr.registration_number = $1
+        order by fe.date_time, fe.id",
+        reg_num
+    )
+    .fetch_all(db)
     .await
     .map_err(Error::DbError)
 }
 
-pub async fn get_user_by_token(
+/// Returns the costs of the maintenance entries of one vehicle, or of the whole fleet without
+/// a registration number. Entries without a cost are left out.
+pub async fn get_maintenance_costs(
     db: &Pool<Postgres>,
-    token: &str,
-) -> Result<Option<user::Model>, Error> {
+    reg_num: Option<&str>,
+) -> Result<Vec<maintenance_history::Cost>, Error> {
     sqlx::query_as!(
-        user::Model,
-        "select distinct u.id, u.display_name, u.email, u.password_hash from \"user\" u 
-         inner join active_session a on u.id = a.user_id 
-         where a.\"token\" = $1",
-        token
+        maintenance_history::Cost,
+        "select cr.registration_number, mh.date_time, mh.cost_cents as \"amount!: Money\"
+        from maintenance_history mh
+        inner join car_registration cr on cr.id = mh.car_id
+        where mh.cost_cents is not null and ($1::varchar is null or cr.registration_number = $1)
+        order by mh.date_time",
+        reg_num
     )
-    .fetch_optional(db)
+    .fetch_all(db)
     .await
     .map_err(Error::DbError)
 }
 
-pub async fn create_token(
+/// Returns how far each vehicle, or only the given one, has been driven according to its
+/// odometer readings.
+pub async fn get_distances(
     db: &Pool<Postgres>,
-    user_id: i32,
-) -> Result<active_session::Model, Error> {
-    let token: String = rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(32)
-        .map(char::from)
-        .collect();
+    reg_num: Option<&str>,
+) -> Result<Vec<mileage_reading::Distance>, Error> {
+    sqlx::query_as!(
+        mileage_reading::Distance,
+        "select cr.registration_number, max(mr.mileage) - min(mr.mileage) as \"distance!\"
+        from mileage_reading mr
+        inner join car_registration cr on cr.id = mr.car_id
+        where $1::varchar is null or cr.registration_number = $1
+        group by cr.registration_number",
+        reg_num
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// A work order that is about to be written to the database.
+pub struct NewWorkOrder<'a> {
+    pub title: &'a str,
+    pub planned_at: NaiveDateTime,
+    pub duration_minutes: i32,
+    pub mechanic_user_id: Option<i32>,
+    pub tasks: Vec<&'a str>,
+    pub created_by_user_id: i32,
+}
+
+/// Inserts a work order together with its task list and returns its id.
+pub async fn insert_work_order(
+    db: &Pool<Postgres>,
+    car_id: i32,
+    order: &NewWorkOrder<'_>,
+) -> Result<i32, Error> {
+    let mut trans = db.begin().await?;
+
+    let id = sqlx::query_scalar!(
+        "insert into work_order (car_id, title, planned_at, duration_minutes, mechanic_user_id, created_by_user_id)
+        values ($1, $2, $3, $4, $5, $6)
+        returning id",
+        car_id,
+        order.title,
+        order.planned_at,
+        order.duration_minutes,
+        order.mechanic_user_id,
+        order.created_by_user_id
+    )
+    .fetch_one(&mut *trans)
+    .await?;
+    for task in &order.tasks {
+        insert_work_order_task_row(&mut *trans, id, task).await?;
+    }
+
+    trans.commit().await?;
+    Ok(id)
+}
+
+pub async fn insert_work_order_task(
+    db: &Pool<Postgres>,
+    work_order_id: i32,
+    description: &str,
+) -> Result<(), Error> {
+    insert_work_order_task_row(db, work_order_id, description).await
+}
 
+async fn insert_work_order_task_row<'e>(
+    executor: impl PgExecutor<'e>,
+    work_order_id: i32,
+    description: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "insert into work_order_task (work_order_id, description) values ($1, $2)",
+        work_order_id,
+        description
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Returns the work orders of one vehicle, or of the whole fleet without a registration number,
+/// that are planned in the given range, earliest first.
+pub async fn get_work_orders(
+    db: &Pool<Postgres>,
+    reg_num: Option<&str>,
+    from: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Result<Vec<work_order::Model>, Error> {
+    sqlx::query_as!(
+        work_order::Model,
+        "select wo.id, cr.registration_number, wo.title, wo.planned_at, wo.duration_minutes,
+            wo.mechanic_user_id, mechanic.display_name as \"mechanic?\", wo.status, wo.maintenance_id,
+            creator.display_name as \"created_by?\"
+        from work_order wo
+        inner join car_registration cr on cr.id = wo.car_id
+        left join \"user\" mechanic on mechanic.id = wo.mechanic_user_id
+        left join \"user\" creator on creator.id = wo.created_by_user_id
+        where ($1::varchar is null or cr.registration_number = $1)
+            and wo.planned_at >= $2 and wo.planned_at < $3
+        order by wo.planned_at, wo.id",
+        reg_num,
+        from,
+        until
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Returns the work orders of a vehicle that are not done yet, earliest first.
+pub async fn get_open_work_orders(
+    db: &Pool<Postgres>,
+    reg_num: &str,
+) -> Result<Vec<work_order::Model>, Error> {
+    sqlx::query_as!(
+        work_order::Model,
+        "select wo.id, cr.registration_number, wo.title, wo.planned_at, wo.duration_minutes,
+            wo.mechanic_user_id, mechanic.display_name as \"mechanic?\", wo.status, wo.maintenance_id,
+            creator.display_name as \"created_by?\"
+        from work_order wo
+        inner join car_registration cr on cr.id = wo.car_id
+        left join \"user\" mechanic on mechanic.id = wo.mechanic_user_id
+        left join \"user\" creator on creator.id = wo.created_by_user_id
+        where cr.registration_number = $1 and wo.status <> 'done'
+        order by wo.planned_at, wo.id",
+        reg_num
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+pub async fn get_work_order(db: &Pool<Postgres>, id: i32) -> Result<work_order::Model, Error> {
+    sqlx::query_as!(
+        work_order::Model,
+        "select wo.id, cr.registration_number, wo.title, wo.planned_at, wo.duration_minutes,
+            wo.mechanic_user_id, mechanic.display_name as \"mechanic?\", wo.status, wo.maintenance_id,
+            creator.display_name as \"created_by?\"
+        from work_order wo
+        inner join car_registration cr on cr.id = wo.car_id
+        left join \"user\" mechanic on mechanic.id = wo.mechanic_user_id
+        left join \"user\" creator on creator.id = wo.created_by_user_id
+        where wo.id = $1",
+        id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(Error::WorkOrderNotFound(id))
+}
+
+/// Returns the tasks of the given work orders in the order they were added.
+pub async fn get_work_order_tasks(
+    db: &Pool<Postgres>,
+    work_order_ids: &[i32],
+) -> Result<Vec<work_order::Task>, Error> {
+    sqlx::query_as!(
+        work_order::Task,
+        "select id, work_order_id, description, done
+        from work_order_task
+        where work_order_id = any($1)
+        order by id",
+        work_order_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Ticks a task off or on again and returns the id of its work order.
+pub async fn set_work_order_task_done(
+    db: &Pool<Postgres>,
+    id: i32,
+    done: bool,
+) -> Result<i32, Error> {
+    sqlx::query_scalar!(
+        "update work_order_task set done = $2 where id = $1 returning work_order_id",
+        id,
+        done
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(Error::WorkOrderTaskNotFound(id))
+}
+
+/// Moves a work order that is not done yet to another status.
+pub async fn set_work_order_status(
+    db: &Pool<Postgres>,
+    id: i32,
+    status: WorkOrderStatus,
+) -> Result<(), Error> {
+    let updated = sqlx::query!(
+        "update work_order set status = $2 where id = $1 and status <> 'done'",
+        id,
+        status.as_str()
+    )
+    .execute(db)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(closed_or_missing(db, id).await?);
+    }
+    Ok(())
+}
+
+/// Tells apart the two reasons a work order could not be changed: it is done, or it does not
+/// exist.
+async fn closed_or_missing<'e>(executor: impl PgExecutor<'e>, id: i32) -> Result<Error, Error> {
+    let exists = sqlx::query_scalar!(
+        "select exists(select 1 from work_order where id = $1) as \"exists!\"",
+        id
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(if exists {
+        Error::WorkOrderClosed(id)
+    } else {
+        Error::WorkOrderNotFound(id)
+    })
+}
+
+/// Marks a work order as done and writes the maintenance entry for it, both or neither. Returns
+/// the id of the maintenance entry.
+pub async fn complete_work_order(
+    conn: &mut PgConnection,
+    id: i32,
+    item: &NewMaintenanceItem<'_>,
+    mileage_confirmed: bool,
+) -> Result<i32, Error> {
+    let mut trans = conn.begin().await?;
+
+    // Locking the row keeps two users from completing the same work order twice.
+    let Some(car_id) = sqlx::query_scalar!(
+        "select car_id from work_order where id = $1 and status <> 'done' for update",
+        id
+    )
+    .fetch_optional(&mut *trans)
+    .await?
+    else {
+        return Err(closed_or_missing(&mut *trans, id).await?);
+    };
+    let maintenance_id =
+        insert_maintenance_item_row(&mut trans, car_id, item, mileage_confirmed).await?;
+    sqlx::query!(
+        "update work_order set status = 'done', maintenance_id = $2 where id = $1",
+        id,
+        maintenance_id
+    )
+    .execute(&mut *trans)
+    .await?;
+
+    trans.commit().await?;
+    Ok(maintenance_id)
+}
+
+/// Deletes a work order with its tasks and returns the registration number of the vehicle it
+/// belonged to. The maintenance entry of a completed work order is kept.
+pub async fn delete_work_order(db: &Pool<Postgres>, id: i32) -> Result<String, Error> {
+    sqlx::query_scalar!(
+        "delete from work_order wo
+        using car_registration cr
+        where wo.id = $1 and cr.id = wo.car_id
+        returning cr.registration_number",
+        id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(Error::WorkOrderNotFound(id))
+}
+
+pub async fn insert_webhook_subscription(
+    db: &Pool<Postgres>,
+    url: &str,
+    description: &str,
+    events: &[String],
+) -> Result<i32, Error> {
+    sqlx::query_scalar!(
+        "insert into webhook_subscription (url, description, secret, events)
+        values ($1, $2, $3, $4)
+        returning id",
+        url,
+        description,
+        generate_token(),
+        events
+    )
+    .fetch_one(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+pub async fn get_webhook_subscriptions(
+    db: &Pool<Postgres>,
+) -> Result<Vec<webhook::Subscription>, Error> {
+    sqlx::query_as!(
+        webhook::Subscription,
+        "select id, url, description, secret, events, active, created_at
+        from webhook_subscription
+        order by id"
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Pauses or resumes a subscription. Deliveries of a paused subscription wait in the queue
+/// until it is resumed.
+pub async fn set_webhook_subscription_active(
+    db: &Pool<Postgres>,
+    id: i32,
+    active: bool,
+) -> Result<(), Error> {
+    let updated = sqlx::query!(
+        "update webhook_subscription set active = $2 where id = $1",
+        id,
+        active
+    )
+    .execute(db)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(Error::WebhookSubscriptionNotFound(id));
+    }
+    Ok(())
+}
+
+/// Deletes a subscription together with its deliveries.
+pub async fn delete_webhook_subscription(db: &Pool<Postgres>, id: i32) -> Result<(), Error> {
+    let deleted = sqlx::query!("delete from webhook_subscription where id = $1", id)
+        .execute(db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(Error::WebhookSubscriptionNotFound(id));
+    }
+    Ok(())
+}
+
+/// Queues a delivery of the payload to every active subscription of the event. Returns how
+/// many were queued.
+pub async fn enqueue_webhook_deliveries<'e>(
+    executor: impl PgExecutor<'e>,
+    event: &str,
+    payload: &str,
+) -> Result<u64, Error> {
+    Ok(sqlx::query!(
+        "insert into webhook_delivery (subscription_id, \"event\", payload)
+        select id, $1, $2 from webhook_subscription where active and $1::varchar = any(events)",
+        event,
+        payload
+    )
+    .execute(executor)
+    .await?
+    .rows_affected())
+}
+
+/// Queues a delivery of the payload to one subscription, whatever events it is subscribed to.
+pub async fn enqueue_webhook_delivery(
+    db: &Pool<Postgres>,
+    subscription_id: i32,
+    event: &str,
+    payload: &str,
+) -> Result<(), Error> {
+    let inserted = sqlx::query!(
+        "insert into webhook_delivery (subscription_id, \"event\", payload)
+        select id, $2, $3 from webhook_subscription where id = $1",
+        subscription_id,
+        event,
+        payload
+    )
+    .execute(db)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(Error::WebhookSubscriptionNotFound(subscription_id));
+    }
+    Ok(())
+}
+
+/// Takes up to `limit` due deliveries of active subscriptions off the queue. They are held
+/// back until `lease_until`, so another server working the same queue does not send them too,
+/// and return to the queue by then if their outcome is never recorded.
+pub async fn claim_webhook_deliveries(
+    db: &Pool<Postgres>,
+    limit: i64,
+    lease_until: NaiveDateTime,
+) -> Result<Vec<webhook::Pending>, Error> {
+    sqlx::query_as!(
+        webhook::Pending,
+        "with claimed as (
+            update webhook_delivery wd
+            set next_attempt_at = $3
+            from webhook_subscription ws
+            where ws.id = wd.subscription_id and wd.id in (
+                select due.id from webhook_delivery due
+                inner join webhook_subscription sub on sub.id = due.subscription_id
+                where due.status = 'pending' and due.next_attempt_at <= $1 and sub.active
+                order by due.next_attempt_at, due.id
+                limit $2
+                for update of due skip locked
+            )
+            returning wd.id, ws.url, ws.secret, wd.\"event\", wd.payload, wd.attempts
+        )
+        -- Returned rows come in no particular order, so the oldest are sent first here.
+        select id as \"id!\", url as \"url!\", secret as \"secret!\", \"event\" as \"event!\",
+            payload as \"payload!\", attempts as \"attempts!\"
+        from claimed order by id",
+        Local::now().naive_local(),
+        limit,
+        lease_until
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// What came of an attempt to send a delivery.
+pub struct WebhookAttempt<'a> {
+    pub response_status: Option<i32>,
+    pub error: Option<&'a str>,
+    /// When to try again, or `None` if the delivery succeeded or is given up on.
+    pub retry_at: Option<NaiveDateTime>,
+}
+
+pub async fn record_webhook_attempt(
+    db: &Pool<Postgres>,
+    id: i32,
+    attempt: &WebhookAttempt<'_>,
+) -> Result<(), Error> {
+    let now = Local::now().naive_local();
+    let status = match (attempt.error, attempt.retry_at) {
+        (None, _) => "delivered",
+        (Some(_), Some(_)) => "pending",
+        (Some(_), None) => "failed",
+    };
+    sqlx::query!(
+        "update webhook_delivery
+        set status = $2::varchar, attempts = attempts + 1, last_attempt_at = $3,
+            response_status = $4, last_error = $5, next_attempt_at = coalesce($6, next_attempt_at),
+            delivered_at = case when $2::varchar = 'delivered' then $3::timestamp end
+        where id = $1",
+        id,
+        status,
+        now,
+        attempt.response_status,
+        attempt.error,
+        attempt.retry_at
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Returns the latest deliveries, newest first.
+pub async fn get_webhook_deliveries(
+    db: &Pool<Postgres>,
+    limit: i64,
+) -> Result<Vec<webhook::Delivery>, Error> {
+    sqlx::query_as!(
+        webhook::Delivery,
+        "select wd.id, wd.subscription_id, ws.url, wd.\"event\", wd.status, wd.attempts, wd.created_at,
+            wd.next_attempt_at, wd.last_attempt_at, wd.response_status, wd.last_error, wd.delivered_at
+        from webhook_delivery wd
+        inner join webhook_subscription ws on ws.id = wd.subscription_id
+        order by wd.created_at desc, wd.id desc
+        limit $1",
+        limit
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Puts a delivery back in the queue to be sent right away, with a fresh set of attempts.
+pub async fn retry_webhook_delivery(db: &Pool<Postgres>, id: i32) -> Result<(), Error> {
+    let updated = sqlx::query!(
+        "update webhook_delivery
+        set status = 'pending', attempts = 0, next_attempt_at = $2
+        where id = $1 and status <> 'delivered'",
+        id,
+        Local::now().naive_local()
+    )
+    .execute(db)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(Error::WebhookDeliveryNotFound(id));
+    }
+    Ok(())
+}
+
+/// Returns the registrations that expire on or before the given date, including those that
+/// have already expired, soonest first.
+pub async fn get_expiring_registrations(
+    db: &Pool<Postgres>,
+    until: NaiveDate,
+) -> Result<Vec<car_registration::Expiring>, Error> {
+    sqlx::query_as!(
+        car_registration::Expiring,
+        "select cr.id, cr.registration_number, cr.make, cr.commercial_descriptons, cr.valid_until as \"valid_until!\",
+            cr.responsible_user_id, u.display_name as \"responsible?\", u.email as \"responsible_email?\"
+        from car_registration cr
+        left join \"user\" u on u.id = cr.responsible_user_id
+        where cr.valid_until <= $1
+        order by cr.valid_until, cr.registration_number",
+        until
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+pub async fn set_responsible_user(
+    conn: &mut PgConnection,
+    reg_num: &str,
+    user_id: Option<i32>,
+) -> Result<(), Error> {
+    let result = sqlx::query!(
+        "update car_registration set responsible_user_id = $1 where registration_number = $2",
+        user_id,
+        reg_num
+    )
+    .execute(conn)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::RegistrationNotFound(reg_num.into()));
+    }
+    Ok(())
+}
+
+/// Records that the reminder `days_before` the registration expires is being sent. Returns
+/// false if it has already been claimed, e.g. by another instance of the app, so every
+/// reminder goes out once.
+pub async fn claim_expiry_notification(
+    db: &Pool<Postgres>,
+    car_id: i32,
+    valid_until: NaiveDate,
+    days_before: i32,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "insert into expiry_notification (car_id, valid_until, days_before)
+        values ($1, $2, $3)
+        on conflict do nothing",
+        car_id,
+        valid_until,
+        days_before
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Gives up a claimed reminder that could not be sent, so the next check tries again.
+pub async fn release_expiry_notification(
+    db: &Pool<Postgres>,
+    car_id: i32,
+    valid_until: NaiveDate,
+    days_before: i32,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "delete from expiry_notification
+        where car_id = $1 and valid_until = $2 and days_before = $3",
+        car_id,
+        valid_until,
+        days_before
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Creates a user with the default role and returns their id.
+pub async fn create_user(
+    conn: &mut PgConnection,
+    email: &str,
+    display_name: &str,
+    password: &str,
+) -> Result<i32, Error> {
+    if sqlx::query_as!(
+        user::Model,
+        "select * from \"user\" u
+        where u.email = $1 or u.display_name = $2
+        limit 1",
+        email,
+        display_name
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .is_some()
+    {
+        return Err(Error::RegistrationError(RegistrationError::AlreadyExists));
+    }
+
+    let password_hash = hash_password(password)?;
+
+    sqlx::query_scalar!(
+        "insert into \"user\" (email, display_name, password_hash)
+                  values ($1, $2, $3)
+                  returning id",
+        email.into(),
+        display_name.into(),
+        password_hash
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(Error::DbError)
+}
+
+fn hash_password(password: &str) -> Result<String, Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let argon2 = Argon2::default();
+
+    Ok(argon2
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+pub async fn set_password(db: &Pool<Postgres>, user_id: i32, password: &str) -> Result<(), Error> {
+    let password_hash = hash_password(password)?;
+    let mut trans = db.begin().await?;
+
+    sqlx::query!(
+        "update \"user\" set password_hash = $1 where id = $2",
+        password_hash,
+        user_id
+    )
+    .execute(&mut *trans)
+    .await?;
+    // Changing the password logs the user out everywhere.
+    sqlx::query!("delete from active_session where user_id = $1", user_id)
+        .execute(&mut *trans)
+        .await?;
+
+    trans.commit().await?;
+    Ok(())
+}
+
+pub async fn set_role<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: i32,
+    role: Role,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "update \"user\" set \"role\" = $1 where id = $2",
+        role.to_string(),
+        user_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_user(db: &Pool<Postgres>, user_id: i32) -> Result<(), Error> {
+    let mut trans = db.begin().await?;
+
+    sqlx::query!("delete from active_session where user_id = $1", user_id)
+        .execute(&mut *trans)
+        .await?;
+    // Keep the maintenance history, it just loses its author.
+    sqlx::query!(
+        "update maintenance_history set author_user_id = null where author_user_id = $1",
+        user_id
+    )
+    .execute(&mut *trans)
+    .await?;
+    sqlx::query!("delete from \"user\" where id = $1", user_id)
+        .execute(&mut *trans)
+        .await?;
+
+    trans.commit().await?;
+    Ok(())
+}
+
+pub async fn get_user_by_email(
+    db: &Pool<Postgres>,
+    email: &str,
+) -> Result<Option<user::Model>, Error> {
+    sqlx::query_as!(
+        user::Model,
+        "select * from \"user\" where email = $1",
+        email
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+pub async fn get_user_by_display_name(
+    db: &Pool<Postgres>,
+    display_name: &str,
+) -> Result<Option<user::Model>, Error> {
+    sqlx::query_as!(
+        user::Model,
+        "select * from \"user\" where display_name = $1",
+        display_name
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+pub async fn get_user_contacts(db: &Pool<Postgres>) -> Result<Vec<user::Contact>, Error> {
+    sqlx::query_as!(
+        user::Contact,
+        "select id, display_name, email from \"user\" order by display_name"
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+pub async fn get_admin_contacts(db: &Pool<Postgres>) -> Result<Vec<user::Contact>, Error> {
+    sqlx::query_as!(
+        user::Contact,
+        "select id, display_name, email from \"user\" where \"role\" = 'admin' order by display_name"
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Returns the token of the user's calendar feed, if they have one.
+pub async fn get_calendar_token(
+    db: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<Option<String>, Error> {
+    sqlx::query_scalar!(
+        "select token from calendar_feed where user_id = $1",
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Gives the user a new calendar feed token, which stops the old feed address from working.
+pub async fn reset_calendar_token(db: &Pool<Postgres>, user_id: i32) -> Result<String, Error> {
+    sqlx::query_scalar!(
+        "insert into calendar_feed (user_id, token) values ($1, $2)
+        on conflict (user_id) do update set token = excluded.token, created_at = now()
+        returning token",
+        user_id,
+        generate_token()
+    )
+    .fetch_one(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+pub async fn delete_calendar_token(db: &Pool<Postgres>, user_id: i32) -> Result<(), Error> {
+    sqlx::query!("delete from calendar_feed where user_id = $1", user_id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_user_by_calendar_token(
+    db: &Pool<Postgres>,
+    token: &str,
+) -> Result<Option<user::Model>, Error> {
+    sqlx::query_as!(
+        user::Model,
+        "select u.* from \"user\" u
+        inner join calendar_feed cf on cf.user_id = u.id
+        where cf.token = $1",
+        token
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Returns the registration numbers of the vehicles the user is responsible for.
+pub async fn get_responsible_registrations(
+    db: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<Vec<String>, Error> {
+    sqlx::query_scalar!(
+        "select registration_number from car_registration where responsible_user_id = $1
+        order by registration_number",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Records what an event of the calendar feed looks like now. Its sequence goes up whenever
+/// the fingerprint differs from the one recorded last time.
+pub async fn revise_calendar_event(
+    conn: &mut PgConnection,
+    uid: &str,
+    fingerprint: &str,
+) -> Result<calendar_event::Revision, Error> {
+    sqlx::query!(
+        "insert into calendar_event (uid, fingerprint) values ($1, $2)
+        on conflict (uid) do update set fingerprint = excluded.fingerprint,
+            \"sequence\" = calendar_event.\"sequence\" + 1, modified_at = now()
+        where calendar_event.fingerprint <> excluded.fingerprint",
+        uid,
+        fingerprint
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query_as!(
+        calendar_event::Revision,
+        "select \"sequence\", modified_at from calendar_event where uid = $1",
+        uid
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(Error::DbError)
+}
+
+pub async fn get_user_by_token(
+    db: &Pool<Postgres>,
+    token: &str,
+) -> Result<Option<user::Model>, Error> {
+    sqlx::query_as!(
+        user::Model,
+        "select distinct u.id, u.display_name, u.email, u.password_hash, u.\"role\" from \"user\" u 
+         inner join active_session a on u.id = a.user_id 
+         where a.\"token\" = $1",
+        token
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+pub async fn get_session_by_token(
+    db: &Pool<Postgres>,
+    token: &str,
+) -> Result<Option<active_session::Model>, Error> {
+    sqlx::query_as!(
+        active_session::Model,
+        "select * from active_session where \"token\" = $1",
+        token
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(Error::DbError)
+}
+
+/// Generates a random alphanumeric string suitable for use as a session or CSRF token.
+pub fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Deletes login sessions. Only expired ones unless `all` is set.
+pub async fn purge_sessions(db: &Pool<Postgres>, all: bool) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        "delete from active_session
+         where $1 or idle_timeout < $2 or absolute_timeout < $2",
+        all,
+        Local::now().naive_local()
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn create_token(
+    db: &Pool<Postgres>,
+    user_id: i32,
+    session: &SessionSettings,
+) -> Result<active_session::Model, Error> {
     sqlx::query_as!(
         active_session::Model,
-        "insert into active_session (user_id, token, idle_timeout, absolute_timeout)
-         values ($1, $2, $3, $4)
+        "insert into active_session (user_id, token, idle_timeout, absolute_timeout, csrf_token)
+         values ($1, $2, $3, $4, $5)
          returning *",
         user_id,
-        token,
-        Local::now().naive_local().add(Duration::hours(2)),
-        Local::now().naive_local().add(Days::new(1))
+        generate_token(),
+        Local::now()
+            .naive_local()
+            .add(Duration::minutes(session.idle_timeout_minutes)),
+        Local::now()
+            .naive_local()
+            .add(Duration::hours(session.absolute_timeout_hours)),
+        generate_token()
     )
     .fetch_one(db)
     .await
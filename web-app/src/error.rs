@@ -38,6 +38,71 @@ pub enum Error {
     DatabaseNotFound,
     #[error("No template provider found.")]
     TemplateNotFound,
+    #[error("The form was submitted without a valid CSRF token. Reload the page and try again.")]
+    CsrfTokenMismatch,
+    #[error("No configuration found.")]
+    ConfigNotFound,
+    #[error("The password must not be empty.")]
+    EmptyPassword,
+    #[error("Failed to read or write a file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to convert from or to JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to convert from or to CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("No maintenance entry with the id {0} could be found.")]
+    MaintenanceItemNotFound(i32),
+    #[error("No mileage reading with the id {0} could be found.")]
+    MileageReadingNotFound(i32),
+    #[error("Invalid mileage: {0}")]
+    InvalidMileage(&'static str),
+    #[error("The mileage is out of order with a reading of {0} km. Check it and confirm it is correct to save it anyway.")]
+    MileageOutOfOrder(i32),
+    #[error("No fuel entry with the id {0} could be found.")]
+    FuelEntryNotFound(i32),
+    #[error("Invalid fuel entry: {0}")]
+    InvalidFuelEntry(&'static str),
+    #[error("Invalid cost: {0}")]
+    InvalidCost(&'static str),
+    #[error("No work order with the id {0} could be found.")]
+    WorkOrderNotFound(i32),
+    #[error("No work order task with the id {0} could be found.")]
+    WorkOrderTaskNotFound(i32),
+    #[error("Work order {0} is already done.")]
+    WorkOrderClosed(i32),
+    #[error("Invalid work order: {0}")]
+    InvalidWorkOrder(&'static str),
+    #[error("No calendar feed has this address. Get the current one from your account page.")]
+    CalendarFeedNotFound,
+    #[error("No webhook subscription with the id {0} could be found.")]
+    WebhookSubscriptionNotFound(i32),
+    #[error("No undelivered webhook delivery with the id {0} could be found.")]
+    WebhookDeliveryNotFound(i32),
+    #[error("Invalid webhook: {0}")]
+    InvalidWebhook(&'static str),
+    #[error("No service plan with the id {0} could be found.")]
+    ServicePlanNotFound(i32),
+    #[error("Invalid service plan: {0}")]
+    InvalidServicePlan(&'static str),
+    #[error("No attachment with the id {0} could be found.")]
+    AttachmentNotFound(i32),
+    #[error("{0}")]
+    Upload(#[from] crate::storage::UploadError),
+    #[error("An error occured whilst storing a file: {0}")]
+    Storage(#[from] crate::storage::StorageError),
+    #[error("Failed to create the PDF: {0}")]
+    Pdf(#[from] printpdf::Error),
+    #[error("Could not send a notification: {0}")]
+    Notify(#[from] crate::notify::NotifyError),
+    #[error("The import failed. {0}")]
+    ImportFailed(String),
+    #[error("Only administrators can do this.")]
+    NotAnAdmin,
+    #[error(
+        "Registrations can only be listed up to {} days ahead.",
+        crate::expiry::MAX_WINDOW_DAYS
+    )]
+    InvalidExpiryWindow,
 }
 
 #[derive(Debug, Error)]
@@ -61,7 +126,29 @@ impl ErrorResponder for Error {
         (
             match self {
                 Error::UserNotLoggedIn => Status::Unauthorized,
-                Error::UserNotFoundEmail(_) | Error::RegistrationNotFound(_) => Status::NotFound,
+                Error::CsrfTokenMismatch | Error::NotAnAdmin => Status::Forbidden,
+                Error::WorkOrderClosed(_) => Status::Conflict,
+                Error::UserNotFoundEmail(_)
+                | Error::RegistrationNotFound(_)
+                | Error::MaintenanceItemNotFound(_)
+                | Error::AttachmentNotFound(_)
+                | Error::MileageReadingNotFound(_)
+                | Error::FuelEntryNotFound(_)
+                | Error::WorkOrderNotFound(_)
+                | Error::WorkOrderTaskNotFound(_)
+                | Error::CalendarFeedNotFound
+                | Error::WebhookSubscriptionNotFound(_)
+                | Error::WebhookDeliveryNotFound(_)
+                | Error::ServicePlanNotFound(_) => Status::NotFound,
+                Error::Upload(_)
+                | Error::InvalidServicePlan(_)
+                | Error::InvalidMileage(_)
+                | Error::MileageOutOfOrder(_)
+                | Error::InvalidFuelEntry(_)
+                | Error::InvalidCost(_)
+                | Error::InvalidWorkOrder(_)
+                | Error::InvalidWebhook(_)
+                | Error::InvalidExpiryWindow => Status::BadRequest,
                 Error::RegistrationError(reg) => return reg.response(),
                 _ => Status::InternalServerError,
             },
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Local};
+use rocket::{
+    fairing::{self, Fairing, Info, Kind},
+    tokio::time::sleep,
+    Build, Orbit, Rocket,
+};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    config::{Config, ExpirySettings},
+    notify::{self, Notifier},
+};
+
+/// Sets up the configured notifier, managed as `Arc<dyn Notifier>`, and runs the expiry
+/// check once on launch and then every day at `expiry.check_hour`.
+pub struct ExpiryFairing;
+
+impl ExpiryFairing {
+    pub fn fairing() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for ExpiryFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Expiry reminders",
+            kind: Kind::Ignite | Kind::Liftoff | Kind::Singleton,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let Some(config) = rocket.state::<Config>() else {
+            error!("The expiry fairing requires the configuration to be loaded first.");
+            return Err(rocket);
+        };
+
+        match notify::from_settings(&config.notifications) {
+            Ok(notifier) => Ok(rocket.manage(notifier)),
+            Err(e) => {
+                error!("Could not set up the notifier: {e}");
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(config), Some(db), Some(notifier)) = (
+            rocket.state::<Config>(),
+            rocket.state::<Pool<Postgres>>(),
+            rocket.state::<Arc<dyn Notifier>>(),
+        ) else {
+            error!("Expiry reminders are disabled, the database or notifier is missing.");
+            return;
+        };
+
+        let settings = config.expiry.clone();
+        let db = db.clone();
+        let notifier = notifier.clone();
+        rocket::tokio::spawn(async move {
+            loop {
+                match super::send_reminders(&db, notifier.as_ref(), &settings).await {
+                    Ok(0) => {}
+                    Ok(sent) => info!("Sent {sent} expiry reminders."),
+                    Err(e) => error!("The expiry check failed: {e}"),
+                }
+                sleep(until_next_check(&settings)).await;
+            }
+        });
+    }
+}
+
+/// How long to wait until the next `check_hour`.
+fn until_next_check(settings: &ExpirySettings) -> std::time::Duration {
+    let now = Local::now().naive_local();
+    let mut next = now
+        .date()
+        .and_hms_opt(settings.check_hour, 0, 0)
+        .unwrap_or(now);
+    if next <= now {
+        next += Duration::days(1);
+    }
+    (next - now).to_std().unwrap_or_default()
+}
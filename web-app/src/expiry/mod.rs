@@ -0,0 +1,142 @@
+use chrono::{Duration, Local, NaiveDate};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    config::ExpirySettings,
+    database::{self as db, entities::car_registration},
+    error::Error,
+    notify::{ExpiryNotice, Notifier},
+};
+
+pub mod fairing;
+
+/// How far ahead registrations can be listed. Ten years is longer than any registration is
+/// valid for.
+pub const MAX_WINDOW_DAYS: i64 = 3650;
+
+/// Parses the period of validity read from a card. Cards store it as `YYYYMMDD`, dates typed
+/// in by hand are also accepted as `YYYY-MM-DD` or `DD.MM.YYYY`.
+pub fn parse_validity(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    ["%Y%m%d", "%Y-%m-%d", "%d.%m.%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+}
+
+/// A registration on the expiring page.
+#[derive(Clone, Debug, Serialize)]
+pub struct ExpiryStatus {
+    #[serde(flatten)]
+    pub registration: car_registration::Expiring,
+    /// Negative once the registration has expired.
+    pub days_left: i64,
+    pub expired: bool,
+}
+
+/// Returns the registrations that expire within the given number of days or have expired.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidExpiryWindow`] if `days` is negative or above [`MAX_WINDOW_DAYS`].
+pub async fn expiring_within(db: &Pool<Postgres>, days: i64) -> Result<Vec<ExpiryStatus>, Error> {
+    let today = Local::now().date_naive();
+    if !(0..=MAX_WINDOW_DAYS).contains(&days) {
+        return Err(Error::InvalidExpiryWindow);
+    }
+    let until = today
+        .checked_add_signed(Duration::days(days))
+        .ok_or(Error::InvalidExpiryWindow)?;
+    let registrations = db::get_expiring_registrations(db, until).await?;
+    Ok(registrations
+        .into_iter()
+        .map(|registration| {
+            let days_left = (registration.valid_until - today).num_days();
+            ExpiryStatus {
+                registration,
+                days_left,
+                expired: days_left < 0,
+            }
+        })
+        .collect())
+}
+
+/// Sends the reminders that are due today and returns how many were sent.
+///
+/// Each registration gets the reminder of the closest `remind_days` it has reached, once per
+/// expiry date. Reminders go to the responsible user or, if there is none, to every admin.
+/// Reminders that fail are logged and retried on the next check.
+pub async fn send_reminders(
+    db: &Pool<Postgres>,
+    notifier: &dyn Notifier,
+    settings: &ExpirySettings,
+) -> Result<usize, Error> {
+    let Some(furthest) = settings.remind_days.iter().max() else {
+        return Ok(0);
+    };
+    let expiring = expiring_within(db, (*furthest).into()).await?;
+    let admins = db::get_admin_contacts(db).await?;
+
+    let mut sent = 0;
+    for status in expiring {
+        let registration = status.registration;
+        let Some(days_before) = settings
+            .remind_days
+            .iter()
+            .copied()
+            .filter(|days| status.days_left <= i64::from(*days))
+            .min()
+        else {
+            continue;
+        };
+        if !db::claim_expiry_notification(
+            db,
+            registration.id,
+            registration.valid_until,
+            days_before,
+        )
+        .await?
+        {
+            continue;
+        }
+
+        let recipients = match (
+            registration.responsible_user_id,
+            registration.responsible,
+            registration.responsible_email,
+        ) {
+            (Some(id), Some(display_name), Some(email)) => vec![db::entities::user::Contact {
+                id,
+                display_name,
+                email,
+            }],
+            _ => admins.clone(),
+        };
+        let notice = ExpiryNotice {
+            registration_number: registration.registration_number,
+            make: registration.make,
+            commercial_descriptons: registration.commercial_descriptons,
+            valid_until: registration.valid_until,
+            days_left: status.days_left,
+            recipients,
+        };
+
+        match notifier.notify(&notice).await {
+            Ok(()) => sent += 1,
+            Err(e) => {
+                error!(
+                    "Could not send the reminder for {}: {e}",
+                    notice.registration_number
+                );
+                db::release_expiry_notification(
+                    db,
+                    registration.id,
+                    registration.valid_until,
+                    days_before,
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(sent)
+}
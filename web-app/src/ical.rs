@@ -0,0 +1,325 @@
+use std::{collections::HashSet, fmt::Write};
+
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    config::Config,
+    database::{
+        self as db,
+        entities::{calendar_event::Revision, service_plan, user},
+    },
+    error::Error,
+    service,
+    work_order::{self, WorkOrderStatus},
+};
+
+const PRODUCT_ID: &str = "-//Vehikular//Calendar feed//EN";
+/// Appended to the ids of events, so they stay unique next to events from other calendars.
+const UID_DOMAIN: &str = "vehikular";
+/// How far back work orders are kept in the feed.
+const DAYS_BACK: i64 = 30;
+/// How far ahead work orders and expiring registrations are put in the feed.
+const DAYS_AHEAD: i64 = 365;
+/// Content lines longer than this many bytes have to be folded.
+const LINE_LIMIT: usize = 75;
+
+/// When an event takes place. Dates are all-day events, date-times are in the local time of
+/// whoever looks at the calendar, like all times stored by this app.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum When {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+/// An entry of the feed. The uid has to stay the same whenever the feed is fetched again, so
+/// calendar apps update the event instead of adding it twice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub uid: String,
+    pub start: When,
+    pub end: When,
+    pub summary: String,
+    pub description: String,
+}
+
+/// Collects the due services, expiring registrations and work orders the user gets to see,
+/// each with its current revision. Admins see the whole fleet, everybody else the vehicles they
+/// are responsible for and the work orders they are the mechanic of.
+pub async fn events(
+    db: &Pool<Postgres>,
+    config: &Config,
+    user: &user::Model,
+) -> Result<Vec<(Event, Revision)>, Error> {
+    let today = Local::now().date_naive();
+    let mut events = Vec::new();
+
+    let responsible: Option<HashSet<String>> = if user.is_admin() {
+        None
+    } else {
+        Some(
+            db::get_responsible_registrations(db, user.id)
+                .await?
+                .into_iter()
+                .collect(),
+        )
+    };
+    let sees = |reg_num: &str| match &responsible {
+        Some(responsible) => responsible.contains(reg_num),
+        None => true,
+    };
+
+    // Plans without a due date are either never done or only go by mileage, neither of which
+    // can be put on a day.
+    for status in service::schedule(db::get_service_progress(db, None).await?, &config.service) {
+        let Some(due_date) = status.due_date else {
+            continue;
+        };
+        if !sees(&status.plan.registration_number) {
+            continue;
+        }
+        let mut description = format!("Every{}.", interval(&status.plan));
+        if let Some(due_mileage) = status.due_mileage {
+            let _ = write!(description, " Also due at {due_mileage} km.");
+        }
+        events.push(Event {
+            uid: format!("service-plan-{}@{UID_DOMAIN}", status.plan.id),
+            start: When::Date(due_date),
+            end: When::Date(due_date + Duration::days(1)),
+            summary: format!(
+                "{} due for {}",
+                status.plan.name, status.plan.registration_number
+            ),
+            description,
+        });
+    }
+
+    for registration in
+        db::get_expiring_registrations(db, today + Duration::days(DAYS_AHEAD)).await?
+    {
+        if !sees(&registration.registration_number) {
+            continue;
+        }
+        let mut description = format!(
+            "{} {}",
+            registration.make, registration.commercial_descriptons
+        );
+        if let Some(responsible) = registration.responsible {
+            let _ = write!(description, "\nResponsible: {responsible}");
+        }
+        events.push(Event {
+            uid: format!("registration-{}-validity@{UID_DOMAIN}", registration.id),
+            start: When::Date(registration.valid_until),
+            end: When::Date(registration.valid_until + Duration::days(1)),
+            summary: format!(
+                "Registration of {} expires",
+                registration.registration_number
+            ),
+            description: description.trim().to_string(),
+        });
+    }
+
+    let orders = db::get_work_orders(
+        db,
+        None,
+        (today - Duration::days(DAYS_BACK))
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default(),
+        (today + Duration::days(DAYS_AHEAD))
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default(),
+    )
+    .await?
+    .into_iter()
+    .filter(|order| order.mechanic_user_id == Some(user.id) || sees(&order.registration_number))
+    .collect();
+    for order in work_order::with_tasks(db, orders).await? {
+        let mut description = format!(
+            "Mechanic: {}\nStatus: {}",
+            order.order.mechanic.as_deref().unwrap_or("Unassigned"),
+            order.order.status.replace('_', " ")
+        );
+        for task in &order.tasks {
+            let check = if task.done { "x" } else { " " };
+            let _ = write!(description, "\n[{check}] {}", task.description);
+        }
+        let done = order.order.status == WorkOrderStatus::Done.as_str();
+        events.push(Event {
+            uid: format!("work-order-{}@{UID_DOMAIN}", order.order.id),
+            start: When::DateTime(order.order.planned_at),
+            end: When::DateTime(
+                order.order.planned_at + Duration::minutes(order.order.duration_minutes.into()),
+            ),
+            summary: format!(
+                "{}{} ({})",
+                if done { "Done: " } else { "" },
+                order.order.title,
+                order.order.registration_number
+            ),
+            description,
+        });
+    }
+
+    let mut trans = db.begin().await?;
+    let mut revised = Vec::with_capacity(events.len());
+    for event in events {
+        let revision =
+            db::revise_calendar_event(&mut trans, &event.uid, &fingerprint(&event)).await?;
+        revised.push((event, revision));
+    }
+    trans.commit().await?;
+
+    Ok(revised)
+}
+
+/// Changes whenever anything a calendar app shows of the event changes.
+fn fingerprint(event: &Event) -> String {
+    hex::encode(Sha256::digest(content(event).join("\n")))
+}
+
+fn interval(plan: &service_plan::Progress) -> String {
+    match (plan.interval_km, plan.interval_months) {
+        (Some(km), Some(months)) => format!(" {km} km or {months} months"),
+        (Some(km), None) => format!(" {km} km"),
+        (None, Some(months)) => format!(" {months} months"),
+        (None, None) => String::new(),
+    }
+}
+
+/// Writes the events as an iCalendar file.
+pub fn render(name: &str, events: &[(Event, Revision)]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{PRODUCT_ID}"),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+    ];
+    for (event, revision) in events {
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", event.uid),
+            format!("DTSTAMP:{stamp}"),
+            format!("SEQUENCE:{}", revision.sequence),
+            format!(
+                "LAST-MODIFIED:{}",
+                revision.modified_at.format("%Y%m%dT%H%M%SZ")
+            ),
+        ]);
+        lines.extend(content(event));
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+/// The properties of an event that calendar apps show.
+fn content(event: &Event) -> [String; 4] {
+    [
+        format!("DTSTART{}", when(event.start)),
+        format!("DTEND{}", when(event.end)),
+        format!("SUMMARY:{}", escape(&event.summary)),
+        format!("DESCRIPTION:{}", escape(&event.description)),
+    ]
+}
+
+fn when(when: When) -> String {
+    match when {
+        When::Date(date) => format!(";VALUE=DATE:{}", date.format("%Y%m%d")),
+        When::DateTime(date_time) => format!(":{}", date_time.format("%Y%m%dT%H%M%S")),
+    }
+}
+
+/// Escapes the characters that have a meaning in text values.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Ends a content line with CRLF and breaks it into lines of at most 75 bytes, each
+/// continuation starting with a space. Lines are only broken between characters.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LIMIT {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn special_characters_are_escaped() {
+        assert_eq!(
+            escape("Oil, filter; done\\checked\r\nnext\nline"),
+            r"Oil\, filter\; done\\checked\nnext\nline"
+        );
+    }
+
+    #[test]
+    fn short_lines_are_only_terminated() {
+        assert_eq!(fold("SUMMARY:Oil change"), "SUMMARY:Oil change\r\n");
+    }
+
+    #[test]
+    fn long_lines_are_folded_at_75_bytes() {
+        let line = "x".repeat(160);
+        let folded = fold(&line);
+
+        let lines: Vec<_> = folded.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].len(), LINE_LIMIT);
+        assert!(lines[1].starts_with(' ') && lines[1].len() == LINE_LIMIT);
+        assert_eq!(lines.concat().replace(' ', ""), line);
+    }
+
+    #[test]
+    fn folding_does_not_split_characters() {
+        let line = format!("{}ä", "x".repeat(LINE_LIMIT - 1));
+        let folded = fold(&line);
+
+        assert_eq!(folded, format!("{}\r\n ä\r\n", "x".repeat(LINE_LIMIT - 1)));
+    }
+
+    #[test]
+    fn events_carry_their_revision() {
+        let event = Event {
+            uid: "work-order-1@vehikular".to_string(),
+            start: When::Date(NaiveDate::from_ymd_opt(2023, 12, 1).unwrap()),
+            end: When::Date(NaiveDate::from_ymd_opt(2023, 12, 2).unwrap()),
+            summary: "Brakes".to_string(),
+            description: String::new(),
+        };
+        let revision = Revision {
+            sequence: 2,
+            modified_at: Utc.from_utc_datetime(
+                &NaiveDate::from_ymd_opt(2023, 11, 30)
+                    .and_then(|date| date.and_hms_opt(8, 15, 0))
+                    .unwrap(),
+            ),
+        };
+
+        let calendar = render("Workshop", &[(event, revision)]);
+        assert!(calendar.contains("SEQUENCE:2\r\n"));
+        assert!(calendar.contains("LAST-MODIFIED:20231130T081500Z\r\n"));
+        assert!(calendar.contains("DTSTART;VALUE=DATE:20231201\r\n"));
+    }
+}
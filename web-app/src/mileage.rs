@@ -0,0 +1,251 @@
+use std::fmt::Write;
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::database::entities::mileage_reading;
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 260.0;
+const LEFT: f64 = 70.0;
+const RIGHT: f64 = 20.0;
+const TOP: f64 = 20.0;
+const BOTTOM: f64 = 40.0;
+const TICKS: u32 = 4;
+
+/// A reading in the timeline of a vehicle.
+#[derive(Clone, Debug, Serialize)]
+pub struct TimelineEntry {
+    #[serde(flatten)]
+    pub reading: mileage_reading::Model,
+    /// The highest earlier reading, if this one is lower. Either the odometer was rolled back
+    /// or one of the two readings is wrong.
+    pub below: Option<i32>,
+    /// Whether the reading is lower than an earlier one and nobody has confirmed it yet.
+    pub warning: bool,
+}
+
+/// Checks the readings, which have to be sorted oldest first, for mileages lower than the
+/// highest earlier one.
+pub fn timeline(readings: Vec<mileage_reading::Model>) -> Vec<TimelineEntry> {
+    let mut highest: Option<i32> = None;
+    readings
+        .into_iter()
+        .map(|reading| {
+            let below = highest.filter(|highest| reading.mileage < *highest);
+            highest = highest.max(Some(reading.mileage));
+            TimelineEntry {
+                warning: below.is_some() && reading.confirmed_at.is_none(),
+                below,
+                reading,
+            }
+        })
+        .collect()
+}
+
+/// Checks whether a new reading fits in with the timeline. Returns the mileage of the reading
+/// it conflicts with if it is lower than an earlier or higher than a later reading.
+pub fn check(timeline: &[TimelineEntry], date_time: NaiveDateTime, mileage: i32) -> Option<i32> {
+    let earlier = timeline
+        .iter()
+        .filter(|entry| entry.reading.date_time <= date_time)
+        .map(|entry| entry.reading.mileage)
+        .max();
+    let later = timeline
+        .iter()
+        .filter(|entry| entry.reading.date_time > date_time)
+        .map(|entry| entry.reading.mileage)
+        .min();
+    match (earlier, later) {
+        (Some(earlier), _) if mileage < earlier => Some(earlier),
+        (_, Some(later)) if mileage > later => Some(later),
+        _ => None,
+    }
+}
+
+/// Draws the readings as a line chart of mileage over time. Readings with a warning are
+/// highlighted.
+pub fn chart(timeline: &[TimelineEntry]) -> String {
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {WIDTH} {HEIGHT}" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="11"><rect width="100%" height="100%" fill="white"/>"#
+    );
+    let (Some(first), Some(last)) = (timeline.first(), timeline.last()) else {
+        let _ = write!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle">No mileage has been recorded.</text></svg>"#,
+            WIDTH / 2.0,
+            HEIGHT / 2.0
+        );
+        return svg;
+    };
+
+    let start = first.reading.date_time;
+    let minutes = |date_time: NaiveDateTime| {
+        f64::from(i32::try_from((date_time - start).num_minutes()).unwrap_or(i32::MAX))
+    };
+    let span = minutes(last.reading.date_time);
+    let lowest = timeline
+        .iter()
+        .map(|e| e.reading.mileage)
+        .min()
+        .unwrap_or(0);
+    let highest = timeline
+        .iter()
+        .map(|e| e.reading.mileage)
+        .max()
+        .unwrap_or(0);
+    let (low, high) = axis_range(lowest, highest);
+
+    let plot_width = WIDTH - LEFT - RIGHT;
+    let plot_height = HEIGHT - TOP - BOTTOM;
+    let x = |date_time: NaiveDateTime| {
+        if span > 0.0 {
+            LEFT + minutes(date_time) / span * plot_width
+        } else {
+            LEFT + plot_width / 2.0
+        }
+    };
+    let y = |mileage: f64| TOP + plot_height - (mileage - low) / (high - low) * plot_height;
+
+    // Grid and mileage labels
+    for tick in 0..=TICKS {
+        let mileage = low + (high - low) * f64::from(tick) / f64::from(TICKS);
+        let _ = write!(
+            svg,
+            r##"<line x1="{LEFT}" y1="{y:.1}" x2="{x2}" y2="{y:.1}" stroke="#ddd"/><text x="{lx}" y="{ty:.1}" text-anchor="end">{mileage:.0} km</text>"##,
+            y = y(mileage),
+            x2 = WIDTH - RIGHT,
+            lx = LEFT - 6.0,
+            ty = y(mileage) + 4.0,
+        );
+    }
+    // Dates of the first and last reading
+    let _ = write!(
+        svg,
+        r#"<text x="{LEFT}" y="{ty}" text-anchor="start">{}</text><text x="{}" y="{ty}" text-anchor="end">{}</text>"#,
+        first.reading.date_time.format("%Y-%m-%d"),
+        WIDTH - RIGHT,
+        last.reading.date_time.format("%Y-%m-%d"),
+        ty = HEIGHT - BOTTOM + 18.0,
+    );
+
+    let points: Vec<_> = timeline
+        .iter()
+        .map(|e| {
+            format!(
+                "{:.1},{:.1}",
+                x(e.reading.date_time),
+                y(f64::from(e.reading.mileage))
+            )
+        })
+        .collect();
+    let _ = write!(
+        svg,
+        r##"<polyline points="{}" fill="none" stroke="#473d35" stroke-width="2"/>"##,
+        points.join(" ")
+    );
+    for entry in timeline {
+        let (color, radius) = if entry.warning {
+            ("#a33", 5)
+        } else if entry.below.is_some() {
+            ("#b80", 4)
+        } else {
+            ("#473d35", 3)
+        };
+        let _ = write!(
+            svg,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="{radius}" fill="{color}"><title>{}: {} km</title></circle>"#,
+            x(entry.reading.date_time),
+            y(f64::from(entry.reading.mileage)),
+            entry.reading.date_time.format("%Y-%m-%d"),
+            entry.reading.mileage
+        );
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Rounds the range of the mileage axis out to whole steps, so the grid lines get round labels.
+fn axis_range(lowest: i32, highest: i32) -> (f64, f64) {
+    let span = f64::from(highest - lowest).max(1000.0);
+    let magnitude = 10_f64.powf((span / f64::from(TICKS)).log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| step * f64::from(TICKS) >= span)
+        .unwrap_or(10.0 * magnitude);
+    let low = (f64::from(lowest) / step).floor() * step;
+    let mut high = low + step * f64::from(TICKS);
+    while high < f64::from(highest) {
+        high += step;
+    }
+    (low, high)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn day(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 5, day)
+            .and_then(|date| date.and_hms_opt(12, 0, 0))
+            .unwrap()
+    }
+
+    fn reading(id: i32, date_time: NaiveDateTime, mileage: i32) -> mileage_reading::Model {
+        mileage_reading::Model {
+            id,
+            date_time,
+            mileage,
+            maintenance_id: None,
+            fuel_entry_id: None,
+            subject: None,
+            note: String::new(),
+            author: None,
+            confirmed_at: None,
+            confirmed_by: None,
+        }
+    }
+
+    #[test]
+    fn readings_below_an_earlier_one_are_flagged() {
+        let entries = timeline(vec![
+            reading(1, day(1), 10_000),
+            reading(2, day(2), 12_000),
+            reading(3, day(3), 11_000),
+            reading(4, day(4), 13_000),
+        ]);
+
+        let below: Vec<_> = entries.iter().map(|entry| entry.below).collect();
+        assert_eq!(below, [None, None, Some(12_000), None]);
+        assert!(entries[2].warning);
+        assert!(!entries[3].warning);
+    }
+
+    #[test]
+    fn confirmed_readings_keep_their_conflict_without_a_warning() {
+        let mut rolled_back = reading(2, day(2), 9_000);
+        rolled_back.confirmed_at = Some(day(3));
+        let entries = timeline(vec![reading(1, day(1), 10_000), rolled_back]);
+
+        assert_eq!(entries[1].below, Some(10_000));
+        assert!(!entries[1].warning);
+    }
+
+    #[test]
+    fn new_readings_have_to_fit_between_their_neighbours() {
+        let entries = timeline(vec![
+            reading(1, day(1), 10_000),
+            reading(2, day(10), 20_000),
+        ]);
+
+        assert_eq!(check(&entries, day(5), 15_000), None);
+        assert_eq!(check(&entries, day(5), 9_000), Some(10_000));
+        assert_eq!(check(&entries, day(5), 21_000), Some(20_000));
+        assert_eq!(check(&entries, day(11), 19_000), Some(20_000));
+        assert_eq!(check(&[], day(5), 0), None);
+    }
+}
@@ -0,0 +1,74 @@
+use lettre::{message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::database::entities::user;
+
+use super::{ExpiryNotice, Notifier, NotifyError};
+
+/// Sends every recipient of a reminder an email.
+pub struct EmailNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl EmailNotifier {
+    pub fn new(smtp_url: &str, from: &str) -> Result<Self, NotifyError> {
+        Ok(Self {
+            transport: AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url)?.build(),
+            from: from
+                .parse()
+                .map_err(|e| NotifyError::Address(from.into(), e))?,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl Notifier for EmailNotifier {
+    /// Recipients that can not be emailed are logged and skipped. The reminder only fails if it
+    /// reached nobody, as it is sent to everyone again when it is retried.
+    async fn notify(&self, notice: &ExpiryNotice) -> Result<(), NotifyError> {
+        if notice.recipients.is_empty() {
+            warn!("{} Nobody to email about it.", notice.summary());
+        }
+        let mut sent = false;
+        let mut failure = None;
+        for recipient in &notice.recipients {
+            match self.send(recipient, notice).await {
+                Ok(()) => sent = true,
+                Err(e) => {
+                    error!(
+                        "Could not email {} about {}: {e}",
+                        recipient.email, notice.registration_number
+                    );
+                    failure = Some(e);
+                }
+            }
+        }
+        match failure {
+            Some(e) if !sent => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl EmailNotifier {
+    async fn send(
+        &self,
+        recipient: &user::Contact,
+        notice: &ExpiryNotice,
+    ) -> Result<(), NotifyError> {
+        let to = Mailbox::new(
+            Some(recipient.display_name.clone()),
+            recipient
+                .email
+                .parse()
+                .map_err(|e| NotifyError::Address(recipient.email.clone(), e))?,
+        );
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(notice.summary())
+            .body(notice.body())?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use super::{ExpiryNotice, Notifier, NotifyError};
+
+/// Writes reminders to the server log. Useful when no mail server is available.
+pub struct LogNotifier;
+
+#[rocket::async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, notice: &ExpiryNotice) -> Result<(), NotifyError> {
+        let recipients: Vec<_> = notice
+            .recipients
+            .iter()
+            .map(|r| r.display_name.as_str())
+            .collect();
+        info!(
+            "{} Responsible: {}",
+            notice.summary(),
+            if recipients.is_empty() {
+                "nobody".to_string()
+            } else {
+                recipients.join(", ")
+            }
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    config::{NotificationSettings, NotifierKind},
+    database::entities::user,
+};
+
+use self::{email::EmailNotifier, log::LogNotifier, webhook::WebhookNotifier};
+
+pub mod email;
+pub mod log;
+pub mod webhook;
+
+/// A way of telling users about registrations that are about to expire.
+#[rocket::async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notice: &ExpiryNotice) -> Result<(), NotifyError>;
+}
+
+#[derive(Debug, Error)]
+pub enum NotifyError {
+    #[error("Invalid email address '{0}': {1}")]
+    Address(String, lettre::address::AddressError),
+    #[error("Could not build the email: {0}")]
+    Email(#[from] lettre::error::Error),
+    #[error("Could not send the email: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("Could not call the webhook: {0}")]
+    Webhook(#[from] reqwest::Error),
+}
+
+/// A reminder that a registration expires soon or has expired.
+#[derive(Clone, Debug, Serialize)]
+pub struct ExpiryNotice {
+    pub registration_number: String,
+    pub make: String,
+    pub commercial_descriptons: String,
+    pub valid_until: NaiveDate,
+    /// Negative once the registration has expired.
+    pub days_left: i64,
+    pub recipients: Vec<user::Contact>,
+}
+
+impl ExpiryNotice {
+    /// A one line description, e.g. "The registration of B-AB-123 expires in 7 days."
+    pub fn summary(&self) -> String {
+        let when = match self.days_left {
+            0 => "expires today".to_string(),
+            1 => "expires tomorrow".to_string(),
+            -1 => "expired yesterday".to_string(),
+            days if days < 0 => format!("expired {} days ago", -days),
+            days => format!("expires in {days} days"),
+        };
+        format!("The registration of {} {when}.", self.registration_number)
+    }
+
+    /// The full text of the reminder.
+    pub fn body(&self) -> String {
+        format!(
+            "{}\n\nVehicle: {} {}\nValid until: {}\n",
+            self.summary(),
+            self.make,
+            self.commercial_descriptons,
+            self.valid_until
+        )
+    }
+}
+
+/// Creates the notifier selected in the configuration.
+///
+/// # Errors
+///
+/// This function will return an error if the sender address or the SMTP url of the email
+/// notifier are invalid, or the HTTP client of the webhook notifier could not be created.
+pub fn from_settings(settings: &NotificationSettings) -> Result<Arc<dyn Notifier>, NotifyError> {
+    Ok(match settings.notifier {
+        NotifierKind::Log => Arc::new(LogNotifier),
+        NotifierKind::Email => Arc::new(EmailNotifier::new(
+            settings.smtp_url.as_deref().unwrap_or_default(),
+            settings.email_from.as_deref().unwrap_or_default(),
+        )?),
+        NotifierKind::Webhook => Arc::new(WebhookNotifier::new(
+            settings.webhook_url.clone().unwrap_or_default(),
+        )?),
+    })
+}
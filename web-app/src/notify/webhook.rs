@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use reqwest::Client;
+
+use super::{ExpiryNotice, Notifier, NotifyError};
+
+/// Posts every reminder as JSON to a URL. Responses other than 2xx count as failures.
+pub struct WebhookNotifier {
+    client: Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Result<Self, NotifyError> {
+        Ok(Self {
+            client: Client::builder().timeout(Duration::from_secs(10)).build()?,
+            url,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notice: &ExpiryNotice) -> Result<(), NotifyError> {
+        self.client
+            .post(&self.url)
+            .json(notice)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
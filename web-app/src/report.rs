@@ -0,0 +1,376 @@
+use chrono::Local;
+use printpdf::{
+    BuiltinFont, Color, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point, Rect, Rgb,
+};
+
+use crate::{
+    config::WorkshopSettings,
+    database::entities::{car_registration, maintenance_history},
+    error::Error,
+};
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const HEADER_HEIGHT: f32 = 28.0;
+const FOOTER_HEIGHT: f32 = 15.0;
+const BODY_SIZE: f32 = 10.0;
+const LABEL_WIDTH: f32 = 75.0;
+
+/// Points to millimetres.
+const PT: f32 = 0.3528;
+
+/// Renders the registration, notes and maintenance history of a vehicle into a PDF.
+///
+/// Every page carries the workshop name and address in its header and a page count in its footer.
+///
+/// # Errors
+///
+/// This function will return an error if the fonts could not be loaded or the document could
+/// not be written.
+pub fn vehicle_report(
+    workshop: &WorkshopSettings,
+    registration: &car_registration::Model,
+    notes: &str,
+    history: &[maintenance_history::Model],
+) -> Result<Vec<u8>, Error> {
+    let title = format!("Vehicle report {}", registration.registration_number);
+    let mut report = Report::new(&title, workshop)?;
+
+    report.heading("Registration");
+    let r = registration;
+    let fields = [
+        ("Registration number", &r.registration_number),
+        ("Issuer state", &r.issuer_state),
+        ("Issuer authority", &r.issuer_authority),
+        ("Document number", &r.document_number),
+        ("Date of first registration", &r.date_of_first_registration),
+        ("Date of registration", &r.date_of_registration),
+        ("Period of validity", &r.period_of_validity),
+        ("Surname or business name", &r.surname_or_business_name),
+        ("Other name or initials", &r.other_name_or_initials),
+        ("Address", &r.address),
+        ("Vehicle owner", &r.vehicles_owner),
+        ("Make", &r.make),
+        ("Vehicle type", &r.vehicle_type),
+        ("Commercial descriptions", &r.commercial_descriptons),
+        (
+            "Vehicle identification number",
+            &r.vehicle_identification_number,
+        ),
+        ("Vehicle category", &r.vechicle_category),
+        ("Type approval number", &r.type_approval_number),
+        ("Colour", &r.colour),
+        ("Maximum speed", &r.maximum_speed),
+        ("Capacity", &r.capacity),
+        ("Max net power", &r.max_net_power),
+        ("Fuel type", &r.fuel_type),
+        ("Power weight ratio", &r.power_weight_ratio),
+        ("Number of seats", &r.number_of_seats),
+        ("Number of standing places", &r.nunmber_of_standing_places),
+        ("Vehicle mass with body", &r.vehicle_mass_with_body),
+        (
+            "Max. technically permissible mass",
+            &r.maximum_technically_laden_mass,
+        ),
+        (
+            "Max. permissible mass in service",
+            &r.maximum_laden_mass_of_the_vehicle_in_service,
+        ),
+        (
+            "Max. mass of the whole combination",
+            &r.maximum_laden_mass_of_the_whole_vehicle_in_service,
+        ),
+        ("Max. towable mass braked", &r.braked),
+        ("Max. towable mass unbraked", &r.unbraked),
+        ("Environmental category", &r.environmental_category),
+    ];
+    for (label, value) in fields {
+        report.field(label, value);
+    }
+
+    report.heading("Notes");
+    if notes.trim().is_empty() {
+        report.paragraph("No notes.", false);
+    } else {
+        for line in notes.lines() {
+            report.paragraph(line, false);
+        }
+    }
+
+    report.heading("Maintenance history");
+    if history.is_empty() {
+        report.paragraph("No maintenance has been recorded.", false);
+    }
+    let mut history: Vec<_> = history.iter().collect();
+    history.sort_by_key(|item| item.date_time);
+    for item in history {
+        report.space(2.0);
+        report.keep_together(3.0 * line_height(BODY_SIZE));
+        report.paragraph(
+            &format!(
+                "{} - {}",
+                item.date_time.format("%Y-%m-%d %H:%M"),
+                item.subject
+            ),
+            true,
+        );
+        report.paragraph(
+            &format!(
+                "Mileage: {} km    Done by: {}",
+                item.mileage
+                    .map_or_else(|| "unknown".to_string(), |m| m.to_string()),
+                item.author.as_deref().unwrap_or("Unknown")
+            ),
+            false,
+        );
+        for line in item.body.lines() {
+            report.paragraph(line, false);
+        }
+    }
+
+    report.finish()
+}
+
+/// A document being laid out from top to bottom, starting a new page whenever one is full.
+struct Report<'a> {
+    doc: PdfDocumentReference,
+    layers: Vec<PdfLayerReference>,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    workshop: &'a WorkshopSettings,
+    title: String,
+    /// Distance of the next line from the bottom of the page.
+    y: f32,
+}
+
+impl<'a> Report<'a> {
+    fn new(title: &str, workshop: &'a WorkshopSettings) -> Result<Self, Error> {
+        let (doc, page, layer) =
+            PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Content");
+        let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+        let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+        let layer = doc.get_page(page).get_layer(layer);
+
+        let report = Self {
+            doc,
+            layers: vec![layer],
+            regular,
+            bold,
+            workshop,
+            title: title.to_string(),
+            y: PAGE_HEIGHT - MARGIN - HEADER_HEIGHT,
+        };
+        report.header();
+        Ok(report)
+    }
+
+    fn layer(&self) -> &PdfLayerReference {
+        self.layers.last().expect("a report always has a page")
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self
+            .doc
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Content");
+        self.layers.push(self.doc.get_page(page).get_layer(layer));
+        self.y = PAGE_HEIGHT - MARGIN - HEADER_HEIGHT;
+        self.header();
+    }
+
+    /// Starts a new page unless there is at least `height` millimetres left on this one.
+    fn keep_together(&mut self, height: f32) {
+        if self.y - height < MARGIN + FOOTER_HEIGHT {
+            self.new_page();
+        }
+    }
+
+    fn space(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    fn header(&self) {
+        let layer = self.layer();
+        let top = PAGE_HEIGHT - MARGIN;
+
+        layer.set_fill_color(brand_color());
+        layer.add_rect(Rect::new(
+            Mm(MARGIN),
+            Mm(top - 1.5),
+            Mm(PAGE_WIDTH - MARGIN),
+            Mm(top),
+        ));
+        layer.set_fill_color(black());
+
+        layer.use_text(
+            &self.workshop.name,
+            16.0,
+            Mm(MARGIN),
+            Mm(top - 9.0),
+            &self.bold,
+        );
+        let mut y = top - 14.0;
+        for line in self
+            .workshop
+            .address
+            .lines()
+            .chain(self.workshop.contact.lines())
+            .take(3)
+        {
+            layer.use_text(line, 8.0, Mm(MARGIN), Mm(y), &self.regular);
+            y -= line_height(8.0);
+        }
+
+        let title_width = text_width(&self.title, 10.0);
+        layer.use_text(
+            &self.title,
+            10.0,
+            Mm(PAGE_WIDTH - MARGIN - title_width),
+            Mm(top - 9.0),
+            &self.bold,
+        );
+
+        rule(layer, top - HEADER_HEIGHT + 4.0);
+    }
+
+    fn heading(&mut self, text: &str) {
+        self.space(4.0);
+        self.keep_together(line_height(13.0) + 2.0 * line_height(BODY_SIZE));
+        self.y -= line_height(13.0);
+        self.layer()
+            .use_text(text, 13.0, Mm(MARGIN), Mm(self.y), &self.bold);
+        self.y -= 2.0;
+    }
+
+    /// A label with its value next to it. Long values wrap within their column.
+    fn field(&mut self, label: &str, value: &str) {
+        let lines = wrap(value, PAGE_WIDTH - 2.0 * MARGIN - LABEL_WIDTH, BODY_SIZE);
+        self.keep_together(line_height(BODY_SIZE));
+        self.y -= line_height(BODY_SIZE);
+        self.layer()
+            .use_text(label, BODY_SIZE, Mm(MARGIN), Mm(self.y), &self.bold);
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                self.keep_together(line_height(BODY_SIZE));
+                self.y -= line_height(BODY_SIZE);
+            }
+            self.layer().use_text(
+                line,
+                BODY_SIZE,
+                Mm(MARGIN + LABEL_WIDTH),
+                Mm(self.y),
+                &self.regular,
+            );
+        }
+    }
+
+    fn paragraph(&mut self, text: &str, bold: bool) {
+        let font = if bold { &self.bold } else { &self.regular }.clone();
+        for line in wrap(text, PAGE_WIDTH - 2.0 * MARGIN, BODY_SIZE) {
+            self.keep_together(line_height(BODY_SIZE));
+            self.y -= line_height(BODY_SIZE);
+            self.layer()
+                .use_text(line, BODY_SIZE, Mm(MARGIN), Mm(self.y), &font);
+        }
+    }
+
+    /// Writes the footers, now that the number of pages is known, and returns the document.
+    fn finish(self) -> Result<Vec<u8>, Error> {
+        let generated = format!("Generated on {}", Local::now().format("%Y-%m-%d %H:%M"));
+        let count = self.layers.len();
+        for (index, layer) in self.layers.iter().enumerate() {
+            rule(layer, MARGIN + FOOTER_HEIGHT - 6.0);
+            layer.use_text(
+                generated.as_str(),
+                8.0,
+                Mm(MARGIN),
+                Mm(MARGIN + 4.0),
+                &self.regular,
+            );
+            let page = format!("Page {} of {count}", index + 1);
+            layer.use_text(
+                page.as_str(),
+                8.0,
+                Mm(PAGE_WIDTH - MARGIN - text_width(&page, 8.0)),
+                Mm(MARGIN + 4.0),
+                &self.regular,
+            );
+        }
+
+        Ok(self.doc.save_to_bytes()?)
+    }
+}
+
+fn brand_color() -> Color {
+    Color::Rgb(Rgb::new(0.278, 0.239, 0.208, None))
+}
+
+fn black() -> Color {
+    Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None))
+}
+
+/// Draws a thin horizontal line across the text area.
+fn rule(layer: &PdfLayerReference, y: f32) {
+    layer.set_outline_color(brand_color());
+    layer.set_outline_thickness(0.5);
+    layer.add_line(Line {
+        points: vec![
+            (Point::new(Mm(MARGIN), Mm(y)), false),
+            (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(y)), false),
+        ],
+        is_closed: false,
+    });
+}
+
+fn line_height(font_size: f32) -> f32 {
+    font_size * PT * 1.3
+}
+
+/// Estimates the width of a text set in Helvetica. The built-in fonts come without metrics, so
+/// this uses the average widths of narrow, regular and wide glyphs.
+fn text_width(text: &str, font_size: f32) -> f32 {
+    let em: f32 = text
+        .chars()
+        .map(|c| match c {
+            'i' | 'j' | 'l' | '.' | ',' | ':' | ';' | '\'' | '|' | '!' => 0.25,
+            ' ' | 'f' | 't' | 'r' | 'I' | '-' | '(' | ')' | '/' => 0.33,
+            'm' | 'w' | 'M' | 'W' => 0.85,
+            c if c.is_uppercase() || c.is_ascii_digit() => 0.68,
+            _ => 0.56,
+        })
+        .sum();
+    em * font_size * PT
+}
+
+/// Breaks the text into lines no wider than `width` millimetres, splitting overlong words.
+fn wrap(text: &str, width: f32, font_size: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{line} {word}")
+        };
+        if text_width(&candidate, font_size) <= width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            line.push(c);
+            if text_width(&line, font_size) > width {
+                line.pop();
+                lines.push(std::mem::take(&mut line));
+                line.push(c);
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
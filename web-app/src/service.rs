@@ -0,0 +1,100 @@
+use chrono::{Local, Months, NaiveDate};
+use serde::Serialize;
+
+use crate::{config::ServiceSettings, database::entities::service_plan};
+
+/// How urgent a service is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DueState {
+    Overdue,
+    DueSoon,
+    Ok,
+}
+
+/// When a service plan is due next and how urgent it is.
+#[derive(Clone, Debug, Serialize)]
+pub struct ServiceStatus {
+    #[serde(flatten)]
+    pub plan: service_plan::Progress,
+    pub due_date: Option<NaiveDate>,
+    pub due_mileage: Option<i32>,
+    /// Whether the service has never been done, in which case it is due right away.
+    pub never_done: bool,
+    pub state: DueState,
+}
+
+impl ServiceStatus {
+    /// Computes the next due date and mileage from the latest matching maintenance entry.
+    pub fn compute(plan: service_plan::Progress, settings: &ServiceSettings) -> Self {
+        let today = Local::now().date_naive();
+        let never_done = plan.last_date_time.is_none();
+
+        let due_date = match (plan.last_date_time, plan.interval_months) {
+            (Some(last), Some(months)) => u32::try_from(months)
+                .ok()
+                .and_then(|months| last.date().checked_add_months(Months::new(months))),
+            _ => None,
+        };
+        let due_mileage = match (plan.last_mileage, plan.interval_km) {
+            (Some(last), Some(km)) => last.checked_add(km),
+            _ => None,
+        };
+
+        let date_state = due_date.map(|due| {
+            let days_left = (due - today).num_days();
+            if days_left < 0 {
+                DueState::Overdue
+            } else if days_left <= settings.due_soon_days {
+                DueState::DueSoon
+            } else {
+                DueState::Ok
+            }
+        });
+        let mileage_state = due_mileage.zip(plan.current_mileage).map(|(due, current)| {
+            if current >= due {
+                DueState::Overdue
+            } else if due - current <= settings.due_soon_km {
+                DueState::DueSoon
+            } else {
+                DueState::Ok
+            }
+        });
+
+        // Whichever of date and mileage comes first decides.
+        let state = if never_done {
+            DueState::Overdue
+        } else {
+            date_state
+                .into_iter()
+                .chain(mileage_state)
+                .min()
+                .unwrap_or(DueState::Ok)
+        };
+
+        Self {
+            plan,
+            due_date,
+            due_mileage,
+            never_done,
+            state,
+        }
+    }
+}
+
+/// Computes the due state of every plan, most urgent first.
+pub fn schedule(
+    plans: Vec<service_plan::Progress>,
+    settings: &ServiceSettings,
+) -> Vec<ServiceStatus> {
+    let mut due: Vec<_> = plans
+        .into_iter()
+        .map(|plan| ServiceStatus::compute(plan, settings))
+        .collect();
+    due.sort_by(|a, b| {
+        a.state
+            .cmp(&b.state)
+            .then_with(|| a.due_date.cmp(&b.due_date))
+    });
+    due
+}
@@ -0,0 +1,50 @@
+use rocket::{
+    fairing::{self, Fairing, Info, Kind},
+    Build, Rocket,
+};
+
+use crate::config::{Config, StorageBackend};
+
+use super::{local::LocalStorage, Storage};
+
+/// Sets up the configured storage backend and manages it as `Box<dyn Storage>`.
+pub struct StorageFairing;
+
+impl StorageFairing {
+    pub fn fairing() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for StorageFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Storage",
+            kind: Kind::Ignite | Kind::Singleton,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let Some(config) = rocket.state::<Config>() else {
+            error!("The storage fairing requires the configuration to be loaded first.");
+            return Err(rocket);
+        };
+
+        let storage: Box<dyn Storage> = match config.attachments.storage {
+            StorageBackend::Local => match LocalStorage::new(config.attachments.path.clone()).await
+            {
+                Ok(storage) => Box::new(storage),
+                Err(e) => {
+                    error!(
+                        "Could not use '{}' to store attachments: {e}",
+                        config.attachments.path.display()
+                    );
+                    return Err(rocket);
+                }
+            },
+        };
+
+        Ok(rocket.manage(storage))
+    }
+}
@@ -0,0 +1,50 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use rocket::tokio::fs;
+
+use super::{Storage, StorageError};
+
+/// Keeps files in a directory on the local filesystem, one file per key.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Uses the given directory, creating it if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the directory could not be created.
+    pub async fn new(root: PathBuf) -> Result<Self, StorageError> {
+        fs::create_dir_all(&root).await?;
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+#[rocket::async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, contents: &[u8]) -> Result<(), StorageError> {
+        fs::write(self.path(key), contents).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        match fs::read(self.path(key)).await {
+            Ok(contents) => Ok(contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(StorageError::NotFound(key.into())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match fs::remove_file(self.path(key)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::{env, io::Cursor};
+
+use rocket::{
+    fs::TempFile,
+    http::{ContentType, Header, Status},
+    response::{self, Responder},
+    tokio::fs,
+    Request, Response,
+};
+use thiserror::Error;
+
+use crate::{config::AttachmentSettings, database::generate_token};
+
+pub mod fairing;
+pub mod local;
+
+/// A place where uploaded files are kept. Files are addressed by a key generated by the app,
+/// so backends never have to deal with user supplied names.
+#[rocket::async_trait]
+pub trait Storage: Send + Sync {
+    /// Stores the contents under the key, replacing anything stored there before.
+    async fn put(&self, key: &str, contents: &[u8]) -> Result<(), StorageError>;
+
+    /// Returns the contents stored under the key.
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    /// Removes the contents stored under the key. Removing a missing key is not an error.
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("No file stored under '{0}'.")]
+    NotFound(String),
+    #[error("Could not access the storage: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// An upload that passed validation and is ready to be stored.
+pub struct Upload {
+    pub file_name: String,
+    pub content_type: &'static str,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("'{0}' is larger than the allowed {1} MiB.")]
+    TooLarge(String, u64),
+    #[error("'{0}' is not one of the accepted file types ({1}).")]
+    TypeNotAllowed(String, String),
+    #[error("Could not read the uploaded file '{0}': {1}")]
+    Io(String, std::io::Error),
+}
+
+/// Reads and validates an uploaded file. Returns `None` for the empty part browsers send
+/// when no file was picked.
+///
+/// The content type is detected from the contents instead of trusting the one sent by the
+/// browser.
+///
+/// # Errors
+///
+/// Returns an error if the file is too large or of a type that is not allowed.
+pub async fn validate(
+    file: &mut TempFile<'_>,
+    settings: &AttachmentSettings,
+) -> Result<Option<Upload>, UploadError> {
+    let file_name = sanitize_file_name(file);
+    if file.len() == 0 {
+        return Ok(None);
+    }
+    if file.len() > settings.max_file_size() {
+        return Err(UploadError::TooLarge(file_name, settings.max_file_size_mib));
+    }
+
+    let contents = read(file)
+        .await
+        .map_err(|e| UploadError::Io(file_name.clone(), e))?;
+
+    match detect_content_type(&contents) {
+        Some(content_type) if settings.allowed_types.iter().any(|t| t == content_type) => {
+            Ok(Some(Upload {
+                file_name,
+                content_type,
+                contents,
+            }))
+        }
+        _ => Err(UploadError::TypeNotAllowed(
+            file_name,
+            settings.allowed_types.join(", "),
+        )),
+    }
+}
+
+/// Stores the uploads under newly generated keys and returns the keys in the same order.
+/// If one of them fails, the ones already stored are removed again.
+///
+/// # Errors
+///
+/// Returns the error of the backend.
+pub async fn put_all(
+    storage: &dyn Storage,
+    uploads: &[Upload],
+) -> Result<Vec<String>, StorageError> {
+    let mut keys = Vec::new();
+    for upload in uploads {
+        let key = generate_token();
+        if let Err(e) = storage.put(&key, &upload.contents).await {
+            delete_all(storage, &keys).await;
+            return Err(e);
+        }
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// Removes the files stored under the keys. Failures are logged instead of returned, as the
+/// database rows pointing at them are already gone or were never written at this point.
+pub async fn delete_all(storage: &dyn Storage, keys: &[String]) {
+    for key in keys {
+        if let Err(e) = storage.delete(key).await {
+            warn!("Could not delete stored file '{key}': {e}");
+        }
+    }
+}
+
+async fn read(file: &mut TempFile<'_>) -> std::io::Result<Vec<u8>> {
+    if let Some(path) = file.path() {
+        return fs::read(path).await;
+    }
+    // Small uploads are kept in memory, which can only be got at by copying them out.
+    let path = env::temp_dir().join(format!("vehikular-upload-{}", generate_token()));
+    file.copy_to(&path).await?;
+    let contents = fs::read(&path).await;
+    fs::remove_file(&path).await?;
+    contents
+}
+
+/// Recognises the accepted file types by their magic numbers.
+fn detect_content_type(contents: &[u8]) -> Option<&'static str> {
+    if contents.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if contents.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if contents.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if contents.len() >= 12 && &contents[..4] == b"RIFF" && &contents[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// The name the file was uploaded with, stripped of any path and of characters that do not
+/// belong in a header.
+fn sanitize_file_name(file: &TempFile<'_>) -> String {
+    let raw = file
+        .raw_name()
+        .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str())
+        .unwrap_or_default();
+    let name: String = raw
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || " ._-()".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(100)
+        .collect();
+    let name = name.trim();
+    if name.is_empty() || name.chars().all(|c| c == '.') {
+        "attachment".into()
+    } else {
+        name.into()
+    }
+}
+
+/// A stored file sent back to the browser.
+pub struct StoredFile {
+    pub file_name: String,
+    pub content_type: String,
+    pub contents: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for StoredFile {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let content_type =
+            ContentType::parse_flexible(&self.content_type).unwrap_or(ContentType::Binary);
+        Response::build()
+            .status(Status::Ok)
+            .header(content_type)
+            .header(Header::new(
+                "Content-Disposition",
+                content_disposition(&self.file_name),
+            ))
+            .sized_body(self.contents.len(), Cursor::new(self.contents))
+            .ok()
+    }
+}
+
+/// Header values have to be ASCII, so the name is given as an ASCII fallback and, percent
+/// encoded, in full.
+fn content_disposition(file_name: &str) -> String {
+    let fallback: String = file_name
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    let encoded: String = file_name
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"._-".contains(&b) {
+                char::from(b).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect();
+    format!("inline; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
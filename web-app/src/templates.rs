@@ -16,8 +16,19 @@ use tera::{Context, Tera};
 use thiserror::Error;
 
 use crate::{
-    database::entities::{car_registration, maintenance_history, user},
+    config::Config,
+    costs::{FleetSummary, VehicleCosts},
+    csrf::CsrfToken,
+    database::entities::{
+        car_registration, maintenance_attachment, maintenance_history, user, webhook,
+    },
     error::Error,
+    expiry::ExpiryStatus,
+    mileage::TimelineEntry,
+    service::ServiceStatus,
+    transfer::ImportReport,
+    webhooks::WebhookEvent,
+    work_order::{Week, WorkOrder},
 };
 
 static TEMPLATE_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/templates");
@@ -141,21 +152,36 @@ impl<'r> PageRenderer<'r> {
     pub async fn index(
         &mut self,
         vehicles: Vec<car_registration::Model>,
+        query: Option<&str>,
     ) -> Result<Webpage, Error> {
         self.context.insert("registrations", &vehicles);
+        self.context.insert("q", &query.unwrap_or_default());
 
         self.render("index").await
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn registration(
         &mut self,
         registration: &car_registration::Model,
         notes: &str,
         history: &Vec<maintenance_history::Model>,
+        attachments: &Vec<maintenance_attachment::Model>,
+        service: &[ServiceStatus],
+        users: &[user::Contact],
+        mileage: &[TimelineEntry],
+        costs: &VehicleCosts,
+        work_orders: &[WorkOrder],
     ) -> Result<Webpage, Error> {
         self.context.insert("registration", &registration);
         self.context.insert("notes", &notes);
         self.context.insert("history", &history);
+        self.context.insert("attachments", &attachments);
+        self.context.insert("service", &service);
+        self.context.insert("users", &users);
+        self.context.insert("mileage", &mileage);
+        self.context.insert("costs", &costs);
+        self.context.insert("work_orders", &work_orders);
 
         self.render("vehicle").await
     }
@@ -172,7 +198,72 @@ impl<'r> PageRenderer<'r> {
         self.render("login").await
     }
 
-    pub async fn account_page(&self) -> Result<Webpage, Error> {
+    pub async fn service(&mut self, due: &[ServiceStatus]) -> Result<Webpage, Error> {
+        self.context.insert("due", &due);
+
+        self.render("service").await
+    }
+
+    pub async fn costs(&mut self, summary: &FleetSummary) -> Result<Webpage, Error> {
+        self.context.insert("summary", &summary);
+
+        self.render("costs").await
+    }
+
+    pub async fn work_order(
+        &mut self,
+        order: &WorkOrder,
+        users: &[user::Contact],
+    ) -> Result<Webpage, Error> {
+        self.context.insert("order", &order);
+        self.context.insert("users", &users);
+
+        self.render("work_order").await
+    }
+
+    pub async fn calendar(&mut self, week: &Week) -> Result<Webpage, Error> {
+        self.context.insert("week", &week);
+
+        self.render("calendar").await
+    }
+
+    pub async fn expiring(
+        &mut self,
+        expiring: &[ExpiryStatus],
+        days: i64,
+    ) -> Result<Webpage, Error> {
+        self.context.insert("expiring", &expiring);
+        self.context.insert("days", &days);
+
+        self.render("expiring").await
+    }
+
+    pub async fn import(&mut self, report: Option<&ImportReport>) -> Result<Webpage, Error> {
+        if let Some(report) = report {
+            self.context.insert("report", report);
+            self.context.insert("imported", &report.imported());
+            self.context.insert("failed", &report.failed());
+        }
+
+        self.render("import").await
+    }
+
+    pub async fn webhooks(
+        &mut self,
+        subscriptions: &[webhook::Subscription],
+        deliveries: &[webhook::Delivery],
+        events: &[WebhookEvent],
+    ) -> Result<Webpage, Error> {
+        self.context.insert("subscriptions", &subscriptions);
+        self.context.insert("deliveries", &deliveries);
+        self.context.insert("events", &events);
+
+        self.render("webhooks").await
+    }
+
+    pub async fn account_page(&mut self, calendar_token: Option<&str>) -> Result<Webpage, Error> {
+        self.context.insert("calendar_token", &calendar_token);
+
         self.render("account_page").await
     }
 
@@ -237,6 +328,15 @@ impl<'r> FromRequest<'r> for PageRenderer<'r> {
             Outcome::Success(user) => context.insert("user", &user),
             Outcome::Failure(_) | Outcome::Forward(_) => {}
         }
+        if let Outcome::Success(config) = req.guard::<&State<Config>>().await {
+            context.insert("currency", &config.costs.currency);
+        }
+
+        match req.guard::<CsrfToken>().await {
+            Outcome::Success(csrf) => context.insert("csrf_token", csrf.value()),
+            Outcome::Failure(f) => return Outcome::Failure(f),
+            Outcome::Forward(f) => return Outcome::Forward(f),
+        }
 
         let guard = req.guard::<&State<Templates>>().await;
         let templates = match guard {
@@ -0,0 +1,692 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+};
+
+use chrono::NaiveDateTime;
+use rocket::{
+    http::{ContentType, Header, Status},
+    response::{self, Responder},
+    Request, Response,
+};
+use serde::{Deserialize, Serialize};
+use shared::data::{
+    CertificateHolder, Engine, ExhaustEmisions, Mass, MaximumTowableMass, PersonalData,
+    Registration, SeatingCapacity, Vehicle, VehicleOwner,
+};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    costs::Money,
+    database::{self as db, NewMaintenanceItem},
+    error::Error,
+    webhooks::{self, WebhookEvent},
+};
+
+/// The format used to move vehicles in and out of the system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField, clap::ValueEnum)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+
+    fn content_type(self) -> ContentType {
+        match self {
+            Format::Json => ContentType::JSON,
+            Format::Csv => ContentType::new("text", "csv"),
+        }
+    }
+}
+
+/// A vehicle with its notes and maintenance history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VehicleRecord {
+    pub registration: Registration,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub maintenance_history: Vec<MaintenanceRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceRecord {
+    pub date_time: NaiveDateTime,
+    pub subject: String,
+    pub body: String,
+    pub mileage: Option<i32>,
+    #[serde(default)]
+    pub cost: Option<Money>,
+    /// Display name of the user who did the maintenance.
+    pub author: Option<String>,
+}
+
+/// Loads the vehicles matching the query, as searched for on the index, with their notes and history.
+pub async fn load(db: &Pool<Postgres>, query: Option<&str>) -> Result<Vec<VehicleRecord>, Error> {
+    let mut records = Vec::new();
+    for registration in db::search_registrations(db, query).await? {
+        let (registration, notes, history) =
+            db::get_registration_with_history_and_notes(db, &registration.registration_number)
+                .await?;
+        records.push(VehicleRecord {
+            registration: registration.try_into()?,
+            notes: notes.map(|n| n.body),
+            maintenance_history: history
+                .into_iter()
+                .map(|item| MaintenanceRecord {
+                    date_time: item.date_time,
+                    subject: item.subject,
+                    body: item.body,
+                    mileage: item.mileage,
+                    cost: item.cost,
+                    author: item.author,
+                })
+                .collect(),
+        });
+    }
+    Ok(records)
+}
+
+/// Serializes the records in the given format.
+pub fn export(records: &[VehicleRecord], format: Format) -> Result<String, Error> {
+    match format {
+        Format::Json => Ok(serde_json::to_string_pretty(records)?),
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for record in records {
+                let row = CsvRow::from_registration(&record.registration, record.notes.as_deref());
+                if record.maintenance_history.is_empty() {
+                    writer.serialize(&row)?;
+                }
+                for item in &record.maintenance_history {
+                    writer.serialize(CsvRow {
+                        maintenance_date_time: item.date_time.format(CSV_DATE_FORMAT).to_string(),
+                        maintenance_subject: item.subject.clone(),
+                        maintenance_body: item.body.clone(),
+                        maintenance_mileage: item
+                            .mileage
+                            .map(|m| m.to_string())
+                            .unwrap_or_default(),
+                        maintenance_cost: item.cost.map(|c| c.to_string()).unwrap_or_default(),
+                        maintenance_author: item.author.clone().unwrap_or_default(),
+                        ..row.clone()
+                    })?;
+                }
+            }
+            let bytes = writer.into_inner().map_err(|e| Error::Io(e.into_error()))?;
+            Ok(String::from_utf8_lossy(&bytes).into_owned())
+        }
+    }
+}
+
+/// A file offered for download.
+pub struct Download {
+    pub file_name: String,
+    pub format: Format,
+    pub body: String,
+}
+
+impl<'r> Responder<'r, 'static> for Download {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .status(Status::Ok)
+            .header(self.format.content_type())
+            .header(Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", self.file_name),
+            ))
+            .sized_body(self.body.len(), Cursor::new(self.body))
+            .ok()
+    }
+}
+
+/// What happened, or would happen, to one vehicle of an import.
+#[derive(Debug, Serialize)]
+pub struct ImportRow {
+    /// Line in the CSV file or position in the JSON array the vehicle started at.
+    pub line: usize,
+    pub registration_number: String,
+    pub maintenance_items: usize,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub rows: Vec<ImportRow>,
+    /// Set if the file as a whole could not be read.
+    pub error: Option<String>,
+}
+
+impl ImportReport {
+    pub fn imported(&self) -> usize {
+        self.rows.iter().filter(|row| row.errors.is_empty()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.rows.len() - self.imported()
+    }
+}
+
+/// Validates and, unless `dry_run` is set, imports the vehicles in the given file.
+///
+/// Every vehicle is checked on its own and is written in its own transaction, so one bad row
+/// does not keep the others from being imported.
+pub async fn import(
+    db: &Pool<Postgres>,
+    contents: &str,
+    format: Format,
+    dry_run: bool,
+) -> Result<ImportReport, Error> {
+    let parsed = match format {
+        Format::Json => parse_json(contents),
+        Format::Csv => parse_csv(contents),
+    };
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(ImportReport {
+                dry_run,
+                rows: Vec::new(),
+                error: Some(e),
+            })
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let mut rows = Vec::new();
+    for (line, record, mut errors) in parsed {
+        let registration_number = record.registration.registration_number.clone();
+        if registration_number.trim().is_empty() {
+            errors.push("The registration number is empty.".into());
+        } else if !seen.insert(registration_number.clone()) {
+            errors.push("The registration number appears more than once in the file.".into());
+        } else if db::get_registration(db, &registration_number)
+            .await?
+            .is_some()
+        {
+            errors.push("A registration with that registration number already exists.".into());
+        }
+
+        let mut history = Vec::new();
+        for item in &record.maintenance_history {
+            if item.mileage.is_some_and(|mileage| mileage < 0) {
+                errors.push(format!("The mileage of '{}' is negative.", item.subject));
+            }
+            let author_user_id = match &item.author {
+                Some(author) => db::get_user_by_display_name(db, author)
+                    .await?
+                    .map(|user| user.id),
+                None => None,
+            };
+            history.push(NewMaintenanceItem {
+                date_time: item.date_time,
+                subject: &item.subject,
+                body: &item.body,
+                mileage: item.mileage,
+                cost: item.cost,
+                author_user_id,
+            });
+        }
+
+        if errors.is_empty() && !dry_run {
+            if let Err(e) = import_vehicle(db, &record, &history).await {
+                errors.push(e.to_string());
+            }
+        }
+
+        rows.push(ImportRow {
+            line,
+            registration_number,
+            maintenance_items: record.maintenance_history.len(),
+            errors,
+        });
+    }
+
+    Ok(ImportReport {
+        dry_run,
+        rows,
+        error: None,
+    })
+}
+
+/// Writes the vehicle and queues its webhook event in one transaction.
+async fn import_vehicle(
+    db: &Pool<Postgres>,
+    record: &VehicleRecord,
+    history: &[NewMaintenanceItem<'_>],
+) -> Result<(), Error> {
+    let mut trans = db.begin().await?;
+    db::import_vehicle(
+        &mut trans,
+        &record.registration,
+        record.notes.as_deref(),
+        history,
+    )
+    .await?;
+    webhooks::emit_registration(
+        &mut trans,
+        WebhookEvent::RegistrationCreated,
+        &record.registration.registration_number,
+    )
+    .await?;
+    trans.commit().await?;
+    Ok(())
+}
+
+type Parsed = Vec<(usize, VehicleRecord, Vec<String>)>;
+
+/// Reads every vehicle of the JSON array on its own, so one that does not fit is reported
+/// without rejecting the others.
+fn parse_json(contents: &str) -> Result<Parsed, String> {
+    let values: Vec<serde_json::Value> =
+        serde_json::from_str(contents).map_err(|e| format!("The file is not valid JSON: {e}"))?;
+    Ok(values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let registration_number = value
+                .pointer("/registration/registration_number")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            match serde_json::from_value(value) {
+                Ok(record) => (index + 1, record, Vec::new()),
+                Err(e) => (
+                    index + 1,
+                    empty_record(registration_number),
+                    vec![format!("Vehicle {}: {e}", index + 1)],
+                ),
+            }
+        })
+        .collect())
+}
+
+/// Groups the CSV rows by registration number. The registration columns are taken from the
+/// first row of every vehicle, each row with a maintenance date adds a history entry. Later rows
+/// whose registration columns differ from the first one are reported, as they either belong to
+/// another vehicle with the same registration number or contradict the first row.
+fn parse_csv(contents: &str) -> Result<Parsed, String> {
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("The file is not valid CSV: {e}"))?
+        .clone();
+    let mut parsed: Parsed = Vec::new();
+    // The index into `parsed` of every registration number, with the vehicle columns of its
+    // first row.
+    let mut vehicles: HashMap<String, (usize, CsvRow)> = HashMap::new();
+
+    for result in reader.records() {
+        let record = result.map_err(|e| format!("The file is not valid CSV: {e}"))?;
+        let line = record
+            .position()
+            .map_or(0, |p| usize::try_from(p.line()).unwrap_or(usize::MAX));
+        let row: CsvRow = match record.deserialize(Some(&headers)) {
+            Ok(row) => row,
+            Err(e) => {
+                let registration_number = headers
+                    .iter()
+                    .position(|h| h == "registration_number")
+                    .and_then(|i| record.get(i))
+                    .unwrap_or_default()
+                    .to_string();
+                parsed.push((line, empty_record(registration_number), vec![e.to_string()]));
+                continue;
+            }
+        };
+
+        let index = match vehicles.get(&row.registration_number) {
+            Some((index, first)) => {
+                if *first != row.vehicle_columns() {
+                    let first_line = parsed[*index].0;
+                    parsed[*index].2.push(format!(
+                        "Line {line}: the vehicle columns differ from those on line {first_line}."
+                    ));
+                    continue;
+                }
+                *index
+            }
+            None => {
+                match row.to_registration() {
+                    Ok(registration) => parsed.push((
+                        line,
+                        VehicleRecord {
+                            registration,
+                            notes: Some(row.notes.clone()).filter(|n| !n.is_empty()),
+                            maintenance_history: Vec::new(),
+                        },
+                        Vec::new(),
+                    )),
+                    Err(e) => {
+                        parsed.push((line, empty_record(row.registration_number.clone()), vec![e]));
+                    }
+                }
+                let index = parsed.len() - 1;
+                vehicles.insert(
+                    row.registration_number.clone(),
+                    (index, row.vehicle_columns()),
+                );
+                index
+            }
+        };
+
+        match row.to_maintenance() {
+            Ok(Some(item)) => parsed[index].1.maintenance_history.push(item),
+            Ok(None) => {}
+            Err(e) => parsed[index].2.push(format!("Line {line}: {e}")),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// A placeholder for a vehicle that could not be read, so its errors can still be reported.
+fn empty_record(registration_number: String) -> VehicleRecord {
+    VehicleRecord {
+        registration: CsvRow {
+            registration_number,
+            vehicles_owner: VehicleOwner::Unknown.to_string(),
+            ..CsvRow::default()
+        }
+        .to_registration()
+        .expect("an empty row with a known owner is valid"),
+        notes: None,
+        maintenance_history: Vec::new(),
+    }
+}
+
+const CSV_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// One line of a CSV export. Vehicles with several maintenance entries span several lines.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+struct CsvRow {
+    registration_number: String,
+    issuer_state: String,
+    issuer_authority: String,
+    document_number: String,
+    date_of_first_registration: String,
+    surname_or_business_name: String,
+    other_name_or_initials: String,
+    address: String,
+    vehicles_owner: String,
+    make: String,
+    vehicle_type: String,
+    commercial_descriptons: String,
+    vehicle_identification_number: String,
+    maximum_technically_laden_mass: String,
+    maximum_laden_mass_of_the_vehicle_in_service: String,
+    maximum_laden_mass_of_the_whole_vehicle_in_service: String,
+    vehicle_mass_with_body: String,
+    period_of_validity: String,
+    date_of_registration: String,
+    type_approval_number: String,
+    capacity: String,
+    max_net_power: String,
+    fuel_type: String,
+    power_weight_ratio: String,
+    number_of_seats: String,
+    nunmber_of_standing_places: String,
+    vechicle_category: String,
+    braked: String,
+    unbraked: String,
+    colour: String,
+    maximum_speed: String,
+    environmental_category: String,
+    notes: String,
+    maintenance_date_time: String,
+    maintenance_subject: String,
+    maintenance_body: String,
+    maintenance_mileage: String,
+    maintenance_cost: String,
+    maintenance_author: String,
+}
+
+impl CsvRow {
+    fn from_registration(registration: &Registration, notes: Option<&str>) -> Self {
+        let r = registration.clone();
+        Self {
+            registration_number: r.registration_number,
+            issuer_state: r.issuer_state,
+            issuer_authority: r.issuer_authority,
+            document_number: r.document_number,
+            date_of_first_registration: r.date_of_first_registration,
+            surname_or_business_name: r.personal_data.certificate_holder.surname_or_business_name,
+            other_name_or_initials: r.personal_data.certificate_holder.other_name_or_initials,
+            address: r.personal_data.certificate_holder.address,
+            vehicles_owner: r.personal_data.vehicles_owner.to_string(),
+            make: r.vehicle.make,
+            vehicle_type: r.vehicle.vehicle_type,
+            commercial_descriptons: r.vehicle.commercial_descriptons,
+            vehicle_identification_number: r.vehicle_identification_number,
+            maximum_technically_laden_mass: r.mass.maximum_technically_permissible_laden_mass,
+            maximum_laden_mass_of_the_vehicle_in_service: r
+                .mass
+                .maximum_permissible_laden_mass_of_the_vehicle_in_service,
+            maximum_laden_mass_of_the_whole_vehicle_in_service: r
+                .mass
+                .maximum_permissible_laden_mass_of_the_whole_vehicle_in_service,
+            vehicle_mass_with_body: r.vehicle_mass_with_body,
+            period_of_validity: r.period_of_validity,
+            date_of_registration: r.date_of_registration,
+            type_approval_number: r.type_approval_number,
+            capacity: r.engine.capacity,
+            max_net_power: r.engine.max_net_power,
+            fuel_type: r.engine.fuel_type,
+            power_weight_ratio: r.power_weight_ratio,
+            number_of_seats: r.seating_capacity.number_of_seats,
+            nunmber_of_standing_places: r.seating_capacity.nunmber_of_standing_places,
+            vechicle_category: r.vechicle_category,
+            braked: r.maximum_towable_mass.braked,
+            unbraked: r.maximum_towable_mass.unbraked,
+            colour: r.colour,
+            maximum_speed: r.maximum_speed,
+            environmental_category: r.exhaust_emissions.environmental_category,
+            notes: notes.unwrap_or_default().to_string(),
+            maintenance_date_time: String::new(),
+            maintenance_subject: String::new(),
+            maintenance_body: String::new(),
+            maintenance_mileage: String::new(),
+            maintenance_cost: String::new(),
+            maintenance_author: String::new(),
+        }
+    }
+
+    /// The row without its maintenance entry, which is the same on every row of a vehicle.
+    fn vehicle_columns(&self) -> Self {
+        Self {
+            maintenance_date_time: String::new(),
+            maintenance_subject: String::new(),
+            maintenance_body: String::new(),
+            maintenance_mileage: String::new(),
+            maintenance_cost: String::new(),
+            maintenance_author: String::new(),
+            ..self.clone()
+        }
+    }
+
+    fn to_registration(&self) -> Result<Registration, String> {
+        let r = self.clone();
+        Ok(Registration {
+            issuer_state: r.issuer_state,
+            issuer_authority: r.issuer_authority,
+            document_number: r.document_number,
+            registration_number: r.registration_number,
+            date_of_first_registration: r.date_of_first_registration,
+            personal_data: PersonalData {
+                certificate_holder: CertificateHolder {
+                    surname_or_business_name: r.surname_or_business_name,
+                    other_name_or_initials: r.other_name_or_initials,
+                    address: r.address,
+                },
+                vehicles_owner: r.vehicles_owner.parse().map_err(|_| {
+                    format!(
+                        "'{}' is not a valid vehicle owner. Use Yes, No or Unknown.",
+                        self.vehicles_owner
+                    )
+                })?,
+            },
+            vehicle: Vehicle {
+                make: r.make,
+                vehicle_type: r.vehicle_type,
+                commercial_descriptons: r.commercial_descriptons,
+            },
+            vehicle_identification_number: r.vehicle_identification_number,
+            mass: Mass {
+                maximum_technically_permissible_laden_mass: r.maximum_technically_laden_mass,
+                maximum_permissible_laden_mass_of_the_vehicle_in_service: r
+                    .maximum_laden_mass_of_the_vehicle_in_service,
+                maximum_permissible_laden_mass_of_the_whole_vehicle_in_service: r
+                    .maximum_laden_mass_of_the_whole_vehicle_in_service,
+            },
+            vehicle_mass_with_body: r.vehicle_mass_with_body,
+            period_of_validity: r.period_of_validity,
+            date_of_registration: r.date_of_registration,
+            type_approval_number: r.type_approval_number,
+            engine: Engine {
+                capacity: r.capacity,
+                max_net_power: r.max_net_power,
+                fuel_type: r.fuel_type,
+            },
+            power_weight_ratio: r.power_weight_ratio,
+            seating_capacity: SeatingCapacity {
+                number_of_seats: r.number_of_seats,
+                nunmber_of_standing_places: r.nunmber_of_standing_places,
+            },
+            vechicle_category: r.vechicle_category,
+            maximum_towable_mass: MaximumTowableMass {
+                braked: r.braked,
+                unbraked: r.unbraked,
+            },
+            colour: r.colour,
+            maximum_speed: r.maximum_speed,
+            exhaust_emissions: ExhaustEmisions {
+                environmental_category: r.environmental_category,
+            },
+        })
+    }
+
+    /// Returns the maintenance entry of this row, if it has one.
+    fn to_maintenance(&self) -> Result<Option<MaintenanceRecord>, String> {
+        if self.maintenance_date_time.trim().is_empty() {
+            return Ok(None);
+        }
+        let date_time = self.maintenance_date_time.trim();
+        let date_time = NaiveDateTime::parse_from_str(date_time, CSV_DATE_FORMAT)
+            .or_else(|_| NaiveDateTime::parse_from_str(date_time, "%Y-%m-%dT%H:%M:%S"))
+            .or_else(|_| NaiveDateTime::parse_from_str(date_time, "%Y-%m-%dT%H:%M"))
+            .map_err(|_| {
+                format!("'{date_time}' is not a valid date. Use the format YYYY-MM-DD HH:MM:SS.")
+            })?;
+        let mileage = match self.maintenance_mileage.trim() {
+            "" => None,
+            mileage => Some(
+                mileage
+                    .parse()
+                    .map_err(|_| format!("'{mileage}' is not a valid mileage."))?,
+            ),
+        };
+        let cost = match self.maintenance_cost.trim() {
+            "" => None,
+            cost => Some(
+                cost.parse()
+                    .map_err(|e| format!("'{cost}' is not a valid cost: {e}."))?,
+            ),
+        };
+
+        Ok(Some(MaintenanceRecord {
+            date_time,
+            subject: self.maintenance_subject.clone(),
+            body: self.maintenance_body.clone(),
+            mileage,
+            cost,
+            author: Some(self.maintenance_author.clone()).filter(|a| !a.is_empty()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str =
+        "registration_number,vehicles_owner,make,maintenance_date_time,maintenance_subject\n";
+
+    #[test]
+    fn csv_rows_of_a_vehicle_are_grouped() {
+        let parsed = parse_csv(&format!(
+            "{HEADER}W-1,Yes,VW,2023-01-01 10:00:00,Oil\n\
+             W-2,No,BMW,,\n\
+             W-1,Yes,VW,2023-06-01 10:00:00,Brakes\n"
+        ))
+        .unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        let (line, record, errors) = &parsed[0];
+        assert_eq!(*line, 2);
+        assert_eq!(record.registration.registration_number, "W-1");
+        assert_eq!(record.maintenance_history.len(), 2);
+        assert!(errors.is_empty());
+        assert!(parsed[1].1.maintenance_history.is_empty());
+    }
+
+    #[test]
+    fn csv_rows_that_contradict_their_vehicle_are_reported() {
+        let parsed = parse_csv(&format!(
+            "{HEADER}W-1,Yes,VW,2023-01-01 10:00:00,Oil\n\
+             W-1,Yes,Audi,2023-06-01 10:00:00,Brakes\n"
+        ))
+        .unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].1.maintenance_history.len(), 1);
+        assert_eq!(
+            parsed[0].2,
+            ["Line 3: the vehicle columns differ from those on line 2."]
+        );
+    }
+
+    #[test]
+    fn csv_errors_are_reported_by_line() {
+        let parsed = parse_csv(&format!(
+            "{HEADER}W-1,Maybe,VW,,\n\
+             W-2,Yes,VW,yesterday,Oil\n"
+        ))
+        .unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].1.registration.registration_number, "W-1");
+        assert!(parsed[0].2[0].contains("'Maybe' is not a valid vehicle owner"));
+        assert!(parsed[1].2[0].starts_with("Line 3: 'yesterday' is not a valid date"));
+    }
+
+    #[test]
+    fn json_vehicles_are_checked_one_by_one() {
+        let registration = serde_json::to_value(empty_record("W-1".into()).registration).unwrap();
+        let contents = serde_json::json!([
+            { "registration": registration },
+            { "registration": { "registration_number": "W-2" } },
+        ])
+        .to_string();
+
+        let parsed = parse_json(&contents).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed[0].2.is_empty());
+        assert_eq!(parsed[1].0, 2);
+        assert_eq!(parsed[1].1.registration.registration_number, "W-2");
+        assert!(parsed[1].2[0].starts_with("Vehicle 2: "));
+    }
+
+    #[test]
+    fn broken_json_rejects_the_file() {
+        assert!(parse_json("[{").is_err());
+    }
+}
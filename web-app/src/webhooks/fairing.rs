@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    tokio::time::sleep,
+    Orbit, Rocket,
+};
+use sqlx::{Pool, Postgres};
+
+use crate::config::Config;
+
+/// Works off the webhook queue in the background, checking for due deliveries every
+/// `webhooks.poll_seconds`.
+pub struct WebhookFairing;
+
+impl WebhookFairing {
+    pub fn fairing() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for WebhookFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Webhook deliveries",
+            kind: Kind::Liftoff | Kind::Singleton,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(config), Some(db)) = (rocket.state::<Config>(), rocket.state::<Pool<Postgres>>())
+        else {
+            error!("Webhook deliveries are disabled, the configuration or database is missing.");
+            return;
+        };
+        let client = match Client::builder()
+            .timeout(Duration::from_secs(config.webhooks.timeout_seconds))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Webhook deliveries are disabled, no HTTP client could be set up: {e}");
+                return;
+            }
+        };
+
+        let settings = config.webhooks.clone();
+        let db = db.clone();
+        rocket::tokio::spawn(async move {
+            loop {
+                match super::deliver_due(&db, &client, &settings).await {
+                    // A full batch means there may be more due deliveries waiting.
+                    Ok(sent) if i64::try_from(sent).unwrap_or(i64::MAX) >= super::BATCH_SIZE => {
+                        continue
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Could not work off the webhook queue: {e}"),
+                }
+                sleep(Duration::from_secs(settings.poll_seconds)).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt::Display;
+
+use chrono::{Duration, Local, NaiveDateTime};
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde::Serialize;
+use sha2::Sha256;
+use sqlx::{PgConnection, Pool, Postgres};
+
+use crate::{
+    config::WebhookSettings,
+    costs::Money,
+    database::{self as db, entities::webhook, NewMaintenanceItem, WebhookAttempt},
+    error::Error,
+};
+
+pub mod fairing;
+
+/// Header carrying the name of the event.
+pub const EVENT_HEADER: &str = "X-Vehikular-Event";
+/// Header carrying the id of the delivery. It stays the same when a delivery is retried, so
+/// receivers can drop duplicates.
+pub const DELIVERY_HEADER: &str = "X-Vehikular-Delivery";
+/// Header carrying `sha256=` followed by the hex encoded HMAC-SHA256 of the request body,
+/// keyed with the secret of the subscription.
+pub const SIGNATURE_HEADER: &str = "X-Vehikular-Signature";
+/// How many due deliveries are taken off the queue at once.
+const BATCH_SIZE: i64 = 20;
+/// How long the longest wait between two attempts may get.
+const MAX_BACKOFF_HOURS: i64 = 24;
+
+/// Something that happened that subscriptions can be notified about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, FromFormField)]
+pub enum WebhookEvent {
+    #[serde(rename = "registration.created")]
+    #[field(value = "registration.created")]
+    RegistrationCreated,
+    #[serde(rename = "registration.updated")]
+    #[field(value = "registration.updated")]
+    RegistrationUpdated,
+    #[serde(rename = "registration.deleted")]
+    #[field(value = "registration.deleted")]
+    RegistrationDeleted,
+    /// Maintenance entries can not be edited, only created and deleted, so there is no
+    /// `maintenance.updated`. Add one together with a route that edits them.
+    #[serde(rename = "maintenance.created")]
+    #[field(value = "maintenance.created")]
+    MaintenanceCreated,
+    #[serde(rename = "maintenance.deleted")]
+    #[field(value = "maintenance.deleted")]
+    MaintenanceDeleted,
+    #[serde(rename = "notes.updated")]
+    #[field(value = "notes.updated")]
+    NotesUpdated,
+    /// Sent by hand from the webhooks page to check that an endpoint works.
+    #[serde(rename = "ping")]
+    #[field(value = "ping")]
+    Ping,
+}
+
+impl WebhookEvent {
+    /// The events subscriptions can choose from.
+    pub const SUBSCRIBABLE: [WebhookEvent; 6] = [
+        WebhookEvent::RegistrationCreated,
+        WebhookEvent::RegistrationUpdated,
+        WebhookEvent::RegistrationDeleted,
+        WebhookEvent::MaintenanceCreated,
+        WebhookEvent::MaintenanceDeleted,
+        WebhookEvent::NotesUpdated,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::RegistrationCreated => "registration.created",
+            WebhookEvent::RegistrationUpdated => "registration.updated",
+            WebhookEvent::RegistrationDeleted => "registration.deleted",
+            WebhookEvent::MaintenanceCreated => "maintenance.created",
+            WebhookEvent::MaintenanceDeleted => "maintenance.deleted",
+            WebhookEvent::NotesUpdated => "notes.updated",
+            WebhookEvent::Ping => "ping",
+        }
+    }
+}
+
+impl Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The body of every delivery.
+#[derive(Debug, Serialize)]
+struct Envelope<'a, T: Serialize> {
+    event: WebhookEvent,
+    occurred_at: NaiveDateTime,
+    data: &'a T,
+}
+
+/// The data of `maintenance.created`.
+#[derive(Debug, Serialize)]
+pub struct MaintenancePayload<'a> {
+    pub id: i32,
+    pub registration_number: &'a str,
+    pub date_time: NaiveDateTime,
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub mileage: Option<i32>,
+    pub cost: Option<Money>,
+    pub author: Option<&'a str>,
+}
+
+impl<'a> MaintenancePayload<'a> {
+    pub fn new(
+        id: i32,
+        registration_number: &'a str,
+        item: &NewMaintenanceItem<'a>,
+        author: Option<&'a str>,
+    ) -> Self {
+        Self {
+            id,
+            registration_number,
+            date_time: item.date_time,
+            subject: item.subject,
+            body: item.body,
+            mileage: item.mileage,
+            cost: item.cost,
+            author,
+        }
+    }
+}
+
+/// The data of events that only concern a vehicle, like `registration.deleted`.
+#[derive(Debug, Serialize)]
+pub struct VehiclePayload<'a> {
+    pub registration_number: &'a str,
+}
+
+/// The data of `maintenance.deleted`.
+#[derive(Debug, Serialize)]
+pub struct DeletedPayload<'a> {
+    pub id: i32,
+    pub registration_number: &'a str,
+}
+
+/// The data of `notes.updated`.
+#[derive(Debug, Serialize)]
+pub struct NotesPayload<'a> {
+    pub registration_number: &'a str,
+    pub body: &'a str,
+}
+
+fn envelope<T: Serialize>(event: WebhookEvent, data: &T) -> Result<String, Error> {
+    Ok(serde_json::to_string(&Envelope {
+        event,
+        occurred_at: Local::now().naive_local(),
+        data,
+    })?)
+}
+
+/// Queues the event for every subscription to it. Call it in the transaction that saves the
+/// change the event is about, so the event is queued exactly when the change is saved.
+pub async fn emit<T: Serialize>(
+    conn: &mut PgConnection,
+    event: WebhookEvent,
+    data: &T,
+) -> Result<(), Error> {
+    let payload = envelope(event, data)?;
+    db::enqueue_webhook_deliveries(conn, event.as_str(), &payload).await?;
+    Ok(())
+}
+
+/// Queues a registration event with the registration as it is saved in the transaction.
+pub async fn emit_registration(
+    conn: &mut PgConnection,
+    event: WebhookEvent,
+    reg_num: &str,
+) -> Result<(), Error> {
+    let registration = db::find_registration(&mut *conn, reg_num)
+        .await?
+        .ok_or_else(|| Error::RegistrationNotFound(reg_num.into()))?;
+    emit(conn, event, &registration).await
+}
+
+/// Queues a ping for one subscription.
+pub async fn ping(db: &Pool<Postgres>, subscription_id: i32) -> Result<(), Error> {
+    let payload = envelope(WebhookEvent::Ping, &serde_json::json!({}))?;
+    db::enqueue_webhook_delivery(db, subscription_id, WebhookEvent::Ping.as_str(), &payload).await
+}
+
+/// Signs the body with the secret of a subscription.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// How long to wait before the next attempt after `attempts` failed ones.
+fn backoff(settings: &WebhookSettings, attempts: i32) -> Duration {
+    let factor = u32::try_from(attempts - 1)
+        .ok()
+        .and_then(|exponent| 2_i64.checked_pow(exponent))
+        .unwrap_or(i64::MAX);
+    // Capped before it becomes a duration, which can not hold every number of seconds.
+    Duration::seconds(
+        settings
+            .retry_base_seconds
+            .saturating_mul(factor)
+            .min(MAX_BACKOFF_HOURS * 60 * 60),
+    )
+}
+
+/// Sends the deliveries that are due and records how each went. Returns how many were sent.
+pub async fn deliver_due(
+    db: &Pool<Postgres>,
+    client: &Client,
+    settings: &WebhookSettings,
+) -> Result<usize, Error> {
+    // Long enough for every delivery of the batch to time out before the lease runs out.
+    let lease = Duration::seconds(
+        i64::try_from(settings.timeout_seconds).unwrap_or(i64::MAX / BATCH_SIZE) * BATCH_SIZE,
+    ) + Duration::minutes(1);
+    let due =
+        db::claim_webhook_deliveries(db, BATCH_SIZE, Local::now().naive_local() + lease).await?;
+
+    for delivery in &due {
+        let outcome = send(client, delivery).await;
+        let attempts = delivery.attempts + 1;
+        let error = outcome.as_ref().err().map(|(_, error)| error.as_str());
+        let retry_at = (error.is_some() && attempts < settings.max_attempts)
+            .then(|| Local::now().naive_local() + backoff(settings, attempts));
+        if let Some(error) = error {
+            if retry_at.is_some() {
+                warn!("Webhook delivery {} failed, retrying: {error}", delivery.id);
+            } else {
+                error!(
+                    "Webhook delivery {} failed {attempts} times, giving up: {error}",
+                    delivery.id
+                );
+            }
+        }
+        db::record_webhook_attempt(
+            db,
+            delivery.id,
+            &WebhookAttempt {
+                response_status: match &outcome {
+                    Ok(status) => Some(*status),
+                    Err((status, _)) => *status,
+                },
+                error,
+                retry_at,
+            },
+        )
+        .await?;
+    }
+
+    Ok(due.len())
+}
+
+/// Posts a delivery. Returns the response status, or the status, if there was a response,
+/// and the reason the delivery failed. Responses other than 2xx count as failures.
+async fn send(client: &Client, delivery: &webhook::Pending) -> Result<i32, (Option<i32>, String)> {
+    let response = client
+        .post(&delivery.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.event)
+        .header(DELIVERY_HEADER, delivery.id)
+        .header(SIGNATURE_HEADER, sign(&delivery.secret, &delivery.payload))
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let status = response.status();
+    let code = i32::from(status.as_u16());
+    if status.is_success() {
+        Ok(code)
+    } else {
+        Err((Some(code), format!("The endpoint answered with {status}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_are_signed_with_hmac_sha256() {
+        // Test case 2 of RFC 4231.
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_with_every_attempt() {
+        let settings = WebhookSettings::default();
+
+        assert_eq!(backoff(&settings, 1), Duration::seconds(30));
+        assert_eq!(backoff(&settings, 2), Duration::seconds(60));
+        assert_eq!(backoff(&settings, 4), Duration::seconds(240));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let settings = WebhookSettings::default();
+
+        assert_eq!(backoff(&settings, 20), Duration::hours(MAX_BACKOFF_HOURS));
+        assert_eq!(backoff(&settings, 200), Duration::hours(MAX_BACKOFF_HOURS));
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    database::{self as db, entities::work_order},
+    error::Error,
+};
+
+/// Where a work order stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, FromFormField)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkOrderStatus {
+    #[field(value = "planned")]
+    Planned,
+    #[field(value = "in_progress")]
+    InProgress,
+    #[field(value = "done")]
+    Done,
+}
+
+impl WorkOrderStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WorkOrderStatus::Planned => "planned",
+            WorkOrderStatus::InProgress => "in_progress",
+            WorkOrderStatus::Done => "done",
+        }
+    }
+}
+
+/// A work order with its task list.
+#[derive(Clone, Debug, Serialize)]
+pub struct WorkOrder {
+    #[serde(flatten)]
+    pub order: work_order::Model,
+    pub tasks: Vec<work_order::Task>,
+}
+
+/// The work orders planned on one day.
+#[derive(Clone, Debug, Serialize)]
+pub struct Day {
+    pub date: NaiveDate,
+    pub orders: Vec<WorkOrder>,
+    /// The planned duration of all work orders that are not done yet.
+    pub open_minutes: i32,
+}
+
+/// How much work is planned for a mechanic in a week.
+#[derive(Clone, Debug, Serialize)]
+pub struct Workload {
+    /// Nobody if the work orders are not assigned yet.
+    pub mechanic: Option<String>,
+    pub orders: usize,
+    pub open_minutes: i32,
+}
+
+/// The work orders of one week, from Monday to Sunday.
+#[derive(Clone, Debug, Serialize)]
+pub struct Week {
+    pub start: NaiveDate,
+    pub previous: NaiveDate,
+    pub next: NaiveDate,
+    pub days: Vec<Day>,
+    /// Sorted by mechanic, unassigned work last.
+    pub workload: Vec<Workload>,
+}
+
+/// Loads the task lists of the work orders.
+pub async fn with_tasks(
+    db: &Pool<Postgres>,
+    orders: Vec<work_order::Model>,
+) -> Result<Vec<WorkOrder>, Error> {
+    let ids: Vec<_> = orders.iter().map(|order| order.id).collect();
+    let mut tasks: HashMap<i32, Vec<work_order::Task>> = HashMap::new();
+    for task in db::get_work_order_tasks(db, &ids).await? {
+        tasks.entry(task.work_order_id).or_default().push(task);
+    }
+
+    Ok(orders
+        .into_iter()
+        .map(|order| WorkOrder {
+            tasks: tasks.remove(&order.id).unwrap_or_default(),
+            order,
+        })
+        .collect())
+}
+
+/// Loads the work orders of the week the given date falls in.
+pub async fn week(db: &Pool<Postgres>, date: NaiveDate) -> Result<Week, Error> {
+    let start = date - Duration::days(i64::from(date.weekday().num_days_from_monday()));
+    let next = start + Duration::days(7);
+    let orders = db::get_work_orders(
+        db,
+        None,
+        start.and_hms_opt(0, 0, 0).unwrap_or_default(),
+        next.and_hms_opt(0, 0, 0).unwrap_or_default(),
+    )
+    .await?;
+
+    Ok(arrange(start, with_tasks(db, orders).await?))
+}
+
+/// Sorts the work orders, which have to be in the week starting at `start`, into days and sums
+/// up the open work per mechanic.
+fn arrange(start: NaiveDate, orders: Vec<WorkOrder>) -> Week {
+    let mut days: Vec<_> = (0..7)
+        .map(|offset| Day {
+            date: start + Duration::days(offset),
+            orders: Vec::new(),
+            open_minutes: 0,
+        })
+        .collect();
+    let mut workload: BTreeMap<Option<String>, Workload> = BTreeMap::new();
+
+    for order in orders {
+        let open = if order.order.status == WorkOrderStatus::Done.as_str() {
+            0
+        } else {
+            order.order.duration_minutes
+        };
+        let load = workload
+            .entry(order.order.mechanic.clone())
+            .or_insert_with(|| Workload {
+                mechanic: order.order.mechanic.clone(),
+                orders: 0,
+                open_minutes: 0,
+            });
+        load.orders += 1;
+        load.open_minutes += open;
+
+        let offset = (order.order.planned_at.date() - start).num_days();
+        if let Some(day) = usize::try_from(offset).ok().and_then(|i| days.get_mut(i)) {
+            day.open_minutes += open;
+            day.orders.push(order);
+        }
+    }
+
+    // None sorts first in a BTreeMap, but unassigned work reads better at the end.
+    let mut workload: Vec<_> = workload.into_values().collect();
+    if workload.first().is_some_and(|load| load.mechanic.is_none()) {
+        workload.rotate_left(1);
+    }
+
+    Week {
+        start,
+        previous: start - Duration::days(7),
+        next: start + Duration::days(7),
+        days,
+        workload,
+    }
+}
+
+/// Writes the task list of a completed work order as the body of its maintenance entry.
+pub fn maintenance_body(tasks: &[work_order::Task], notes: &str) -> String {
+    let mut body: Vec<_> = tasks
+        .iter()
+        .map(|task| {
+            let check = if task.done { "x" } else { " " };
+            format!("- [{check}] {}", task.description)
+        })
+        .collect();
+    let notes = notes.trim();
+    if !notes.is_empty() {
+        if !body.is_empty() {
+            body.push(String::new());
+        }
+        body.push(notes.to_string());
+    }
+    body.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The Monday the test weeks start on.
+    fn monday() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 11, 27).unwrap()
+    }
+
+    fn order(day: i64, minutes: i32, mechanic: Option<&str>, status: WorkOrderStatus) -> WorkOrder {
+        WorkOrder {
+            order: work_order::Model {
+                id: 0,
+                registration_number: "W-1".to_string(),
+                title: "Brakes".to_string(),
+                planned_at: (monday() + Duration::days(day))
+                    .and_hms_opt(9, 0, 0)
+                    .unwrap(),
+                duration_minutes: minutes,
+                mechanic_user_id: None,
+                mechanic: mechanic.map(str::to_string),
+                status: status.as_str().to_string(),
+                maintenance_id: None,
+                created_by: None,
+            },
+            tasks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn orders_are_sorted_into_their_days() {
+        let week = arrange(
+            monday(),
+            vec![
+                order(0, 60, Some("Ann"), WorkOrderStatus::Planned),
+                order(0, 30, Some("Ann"), WorkOrderStatus::Done),
+                order(4, 90, None, WorkOrderStatus::InProgress),
+            ],
+        );
+
+        assert_eq!(week.days.len(), 7);
+        assert_eq!(week.days[0].orders.len(), 2);
+        assert_eq!(week.days[0].open_minutes, 60);
+        assert_eq!(week.days[4].date, monday() + Duration::days(4));
+        assert_eq!(week.days[4].open_minutes, 90);
+        assert_eq!(week.previous, monday() - Duration::days(7));
+        assert_eq!(week.next, monday() + Duration::days(7));
+    }
+
+    #[test]
+    fn unassigned_work_comes_last_in_the_workload() {
+        let week = arrange(
+            monday(),
+            vec![
+                order(1, 45, None, WorkOrderStatus::Planned),
+                order(2, 60, Some("Bea"), WorkOrderStatus::Planned),
+                order(3, 30, Some("Ann"), WorkOrderStatus::Done),
+            ],
+        );
+
+        let mechanics: Vec<_> = week
+            .workload
+            .iter()
+            .map(|load| load.mechanic.as_deref())
+            .collect();
+        assert_eq!(mechanics, [Some("Ann"), Some("Bea"), None]);
+        assert_eq!(week.workload[0].orders, 1);
+        assert_eq!(week.workload[0].open_minutes, 0);
+        assert_eq!(week.workload[2].open_minutes, 45);
+    }
+
+    #[test]
+    fn orders_outside_the_week_are_left_out_of_the_days() {
+        let week = arrange(monday(), vec![order(7, 60, None, WorkOrderStatus::Planned)]);
+
+        assert!(week.days.iter().all(|day| day.orders.is_empty()));
+    }
+
+    #[test]
+    fn the_maintenance_body_lists_tasks_and_notes() {
+        let tasks = [
+            work_order::Task {
+                id: 1,
+                work_order_id: 1,
+                description: "Pads".to_string(),
+                done: true,
+            },
+            work_order::Task {
+                id: 2,
+                work_order_id: 1,
+                description: "Discs".to_string(),
+                done: false,
+            },
+        ];
+
+        assert_eq!(
+            maintenance_body(&tasks, " Discs next time. "),
+            "- [x] Pads\n- [ ] Discs\n\nDiscs next time."
+        );
+        assert_eq!(maintenance_body(&[], "Only notes"), "Only notes");
+    }
+}